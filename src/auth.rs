@@ -0,0 +1,309 @@
+//! HTTP authentication and apt auth.conf parsing.
+
+#[cfg(not(test))]
+use log::warn;
+
+#[cfg(test)]
+use std::println as warn;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::{Error, ErrorType, Result};
+
+/// Authentication method used for HTTP requests.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Authentication {
+    /// HTTP basic authentication.
+    Basic { login: String, password: String },
+    /// Bearer token, sent as _Authorization: Bearer_ header.
+    Bearer { token: String },
+}
+
+/// Credentials for the URLs of a machine.
+///
+/// The _machine_ uses the format of apt's auth.conf, i.e. _host[:port][/path]_,
+/// optionally prefixed with a scheme, e.g. _https://example.com/debian_.
+/// Like apt, entries without scheme only match _https_ URLs,
+/// to avoid sending credentials unencrypted.
+/// If a path is given, it must be a prefix of the URL path.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Credentials {
+    pub machine: String,
+    pub authentication: Authentication,
+}
+
+impl Credentials {
+    /// Create basic authentication credentials for the machine.
+    pub fn basic(machine: &str, login: &str, password: &str) -> Credentials {
+        Credentials {
+            machine: machine.to_string(),
+            authentication: Authentication::Basic {
+                login: login.to_string(),
+                password: password.to_string(),
+            },
+        }
+    }
+
+    /// Create bearer token credentials for the machine.
+    pub fn bearer(machine: &str, token: &str) -> Credentials {
+        Credentials {
+            machine: machine.to_string(),
+            authentication: Authentication::Bearer {
+                token: token.to_string(),
+            },
+        }
+    }
+
+    /// Check if the credentials shall be used for the URL.
+    pub fn matches(&self, url: &str) -> bool {
+        let url = match Url::parse(url) {
+            Ok(url) => url,
+            Err(_) => return false,
+        };
+
+        let (scheme, machine) = match self.machine.find("://") {
+            Some(pos) => (Some(&self.machine[..pos]), &self.machine[(pos + 3)..]),
+            None => (None, self.machine.as_str()),
+        };
+
+        match scheme {
+            Some(scheme) => {
+                if scheme != url.scheme() {
+                    return false;
+                }
+            }
+            None => {
+                if url.scheme() != "https" && url.scheme() != "tor+https" {
+                    return false;
+                }
+            }
+        }
+
+        let (host, path) = match machine.find('/') {
+            Some(pos) => (&machine[..pos], &machine[pos..]),
+            None => (machine, ""),
+        };
+
+        let (host, port) = match host.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse::<u16>().ok()),
+            None => (host, None),
+        };
+
+        if !url.host_str().is_some_and(|h| h.eq_ignore_ascii_case(host)) {
+            return false;
+        }
+
+        if port.is_some() && port != url.port_or_known_default() {
+            return false;
+        }
+
+        url.path().starts_with(path)
+    }
+}
+
+/// Find the credentials for the URL.
+///
+/// The first matching credentials are used, like in apt.
+pub fn find_credentials<'a>(credentials: &'a [Credentials], url: &str) -> Option<&'a Credentials> {
+    credentials.iter().find(|c| c.matches(url))
+}
+
+/// Parse the content of an apt auth.conf file.
+///
+/// The file uses the netrc format, i.e. the tokens _machine_, _login_ and _password_
+/// followed by the value, separated by whitespace, and comments starting with _#_.
+pub fn parse_auth_conf(content: &str) -> Result<Vec<Credentials>> {
+    let mut credentials = Vec::new();
+    let mut entry: Option<(String, Option<String>, Option<String>)> = None;
+
+    // Comments start with a token beginning with "#", and end at the end of the line.
+    let tokens: Vec<&str> = content
+        .lines()
+        .flat_map(|line| {
+            line.split_whitespace()
+                .take_while(|token| !token.starts_with('#'))
+        })
+        .collect();
+
+    let finish = |entry: Option<(String, Option<String>, Option<String>)>,
+                  credentials: &mut Vec<Credentials>|
+     -> Result<()> {
+        if let Some((machine, login, password)) = entry {
+            match (login, password) {
+                (Some(login), Some(password)) => {
+                    credentials.push(Credentials::basic(&machine, &login, &password))
+                }
+                _ => {
+                    return Err(Error::new(
+                        &format!("Login or password missing for machine {machine}!"),
+                        ErrorType::AuthConf,
+                    ))
+                }
+            }
+        }
+        Ok(())
+    };
+
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        let value = tokens.next().ok_or_else(|| {
+            Error::new(
+                &format!("Value of token {token} missing!"),
+                ErrorType::AuthConf,
+            )
+        })?;
+
+        match token {
+            "machine" => {
+                finish(entry.take(), &mut credentials)?;
+                entry = Some((value.to_string(), None, None));
+            }
+            "login" | "password" => match &mut entry {
+                Some((_, login, password)) => {
+                    if token == "login" {
+                        *login = Some(value.to_string());
+                    } else {
+                        *password = Some(value.to_string());
+                    }
+                }
+                None => {
+                    return Err(Error::new(
+                        &format!("Token {token} found before machine!"),
+                        ErrorType::AuthConf,
+                    ))
+                }
+            },
+            _ => {
+                return Err(Error::new(
+                    &format!("Unknown token {token}!"),
+                    ErrorType::AuthConf,
+                ))
+            }
+        }
+    }
+
+    finish(entry, &mut credentials)?;
+
+    Ok(credentials)
+}
+
+/// Read the credentials of an apt configuration directory, e.g. _/etc/apt_.
+///
+/// The files of _auth.conf.d_ with the extension _.conf_ are read in alphabetical order,
+/// followed by the _auth.conf_ file. Missing files are ignored.
+pub fn read_auth_conf(directory: &str) -> Result<Vec<Credentials>> {
+    let directory = Path::new(directory);
+    let mut files = Vec::new();
+
+    if let Ok(entries) = fs::read_dir(directory.join("auth.conf.d")) {
+        let mut parts: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|e| e == "conf"))
+            .collect();
+        parts.sort();
+        files.extend(parts);
+    }
+    files.push(directory.join("auth.conf"));
+
+    let mut credentials = Vec::new();
+    for file in files {
+        let content = match fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(e) => {
+                if file.exists() {
+                    warn!("Reading {} failed! {e}", file.display());
+                }
+                continue;
+            }
+        };
+
+        let entries = parse_auth_conf(&content).map_err(|e| {
+            Error::new(
+                &format!("{}: {}", file.display(), e.message().unwrap_or_default()),
+                ErrorType::AuthConf,
+            )
+        })?;
+        credentials.extend(entries);
+    }
+
+    Ok(credentials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn parse_netrc_format() {
+        let content = "# Private feed\nmachine example.com/debian\nlogin user\npassword secret\n\nmachine http://other.example.com:8080 login other password pw # comment\n";
+        let credentials = parse_auth_conf(content).unwrap();
+
+        assert_eq!(
+            credentials,
+            vec![
+                Credentials::basic("example.com/debian", "user", "secret"),
+                Credentials::basic("http://other.example.com:8080", "other", "pw"),
+            ]
+        );
+
+        assert!(parse_auth_conf("machine example.com login user\n").is_err());
+        assert!(parse_auth_conf("login user password secret\n").is_err());
+        assert!(parse_auth_conf("machine example.com login\n").is_err());
+        assert!(parse_auth_conf("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn match_urls() {
+        let credentials = Credentials::basic("example.com/debian", "user", "secret");
+        assert!(credentials.matches("https://example.com/debian/dists/stable/InRelease"));
+        assert!(credentials.matches("https://EXAMPLE.com:443/debian/pool/a.deb"));
+        // No credentials for plain HTTP without explicit scheme.
+        assert!(!credentials.matches("http://example.com/debian/dists/stable/InRelease"));
+        assert!(!credentials.matches("https://example.com/ubuntu/dists/jammy/InRelease"));
+        assert!(!credentials.matches("https://example.org/debian/dists/stable/InRelease"));
+
+        let credentials = Credentials::bearer("http://example.com:8080", "token");
+        assert!(credentials.matches("http://example.com:8080/debian/InRelease"));
+        assert!(!credentials.matches("http://example.com/debian/InRelease"));
+        assert!(!credentials.matches("https://example.com:8080/debian/InRelease"));
+        assert!(!credentials.matches("/srv/mirror/InRelease"));
+    }
+
+    #[test]
+    fn read_directory() {
+        let dir = temp_dir("auth");
+        fs::create_dir_all(dir.join("auth.conf.d")).unwrap();
+        fs::write(
+            dir.join("auth.conf.d/20-b.conf"),
+            "machine example.com login b password b\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("auth.conf.d/10-a.conf"),
+            "machine example.com login a password a\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("auth.conf.d/ignored.txt"),
+            "machine example.com login c password c\n",
+        )
+        .unwrap();
+
+        let credentials = read_auth_conf(dir.to_str().unwrap()).unwrap();
+        assert_eq!(credentials.len(), 2);
+        assert_eq!(
+            find_credentials(&credentials, "https://example.com/InRelease"),
+            Some(&Credentials::basic("example.com", "a", "a"))
+        );
+
+        fs::write(dir.join("auth.conf"), "machine\n").unwrap();
+        assert!(read_auth_conf(dir.to_str().unwrap()).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
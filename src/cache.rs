@@ -0,0 +1,543 @@
+//! Disk cache for downloaded files.
+
+#[cfg(not(test))]
+use log::{info, warn};
+
+#[cfg(test)]
+use std::{println as info, println as warn};
+
+use async_trait::async_trait;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::transport::{
+    ChunkReader, Conditional, ConditionalReader, FileChunks, Transport, Validators,
+};
+use crate::util::{is_local, parse_stanza};
+use crate::{Error, Result};
+
+/// Transport storing downloaded files in a cache directory.
+///
+/// The cache is similar to `/var/lib/apt/lists`.
+/// Each file is stored, keyed by the URL, together with its _ETag_ and _Last-Modified_ validators.
+/// If a cached file exists, the file is revalidated using a conditional download
+/// of the wrapped transport, and the cached content is used if it is unchanged.
+/// Acquire-By-Hash URLs are immutable, and served from the cache without revalidation.
+/// Opened URLs are streamed from the cache file, or stored while they are read,
+/// so that large indices are never buffered in memory.
+///
+/// The cached content is verified, i.e. using the signature or the hashes,
+/// in the same way as downloaded content. Content which fails the verification
+/// is removed from the cache using [Transport::evict].
+/// Local URLs are not cached.
+#[derive(Debug, Clone)]
+pub struct CachedTransport {
+    inner: Arc<dyn Transport>,
+    directory: PathBuf,
+}
+
+impl CachedTransport {
+    /// Create a new cache in the given directory, wrapping the given transport.
+    pub fn new(inner: Arc<dyn Transport>, directory: &str) -> CachedTransport {
+        CachedTransport {
+            inner,
+            directory: PathBuf::from(directory),
+        }
+    }
+
+    /// Get the cache file name of the URL.
+    ///
+    /// Like apt, the scheme is removed and slashes are replaced with underscores.
+    /// Underscores and percent signs of the URL are escaped.
+    pub(crate) fn file_name(url: &str) -> String {
+        let url = match url.find("://") {
+            Some(pos) => &url[(pos + 3)..],
+            None => url,
+        };
+
+        url.replace('%', "%25")
+            .replace('_', "%5f")
+            .replace('/', "_")
+    }
+
+    /// Get the path of the cached content of the URL.
+    pub fn path(&self, url: &str) -> PathBuf {
+        self.directory.join(CachedTransport::file_name(url))
+    }
+
+    /// Get the path of the validators of a cached file.
+    fn validators_path(path: &Path) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        path.push(".validators");
+        PathBuf::from(path)
+    }
+
+    /// Get the path of the temporary file used while writing a file.
+    fn partial_path(path: &Path) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        path.push(".partial");
+        PathBuf::from(path)
+    }
+
+    /// Read the validators of the cached content of the URL.
+    ///
+    /// Returns None if the URL is not cached.
+    fn read_validators(&self, url: &str) -> Option<Validators> {
+        let path = self.path(url);
+
+        if !path.is_file() {
+            return None;
+        }
+
+        let validators = fs::read_to_string(CachedTransport::validators_path(&path)).ok()?;
+        let kv = parse_stanza(&validators);
+
+        Some(Validators {
+            etag: kv.get("etag").cloned(),
+            last_modified: kv.get("last-modified").cloned(),
+        })
+    }
+
+    /// Read the cached content and validators of the URL.
+    fn read(&self, url: &str) -> Option<(Vec<u8>, Validators)> {
+        let validators = self.read_validators(url)?;
+        let data = fs::read(self.path(url)).ok()?;
+        Some((data, validators))
+    }
+
+    /// Open the cached content of the URL.
+    fn open_cached(&self, url: &str) -> Result<Box<dyn ChunkReader>> {
+        let path = self.path(url);
+        Ok(Box::new(FileChunks::open(&path.to_string_lossy(), url)?))
+    }
+
+    /// Store the content and the validators of the URL.
+    ///
+    /// The files are written to temporary files first, and renamed afterwards.
+    fn write(&self, url: &str, data: &[u8], validators: &Validators) -> Result<()> {
+        let path = self.path(url);
+        let partial = CachedTransport::partial_path(&path);
+
+        fs::create_dir_all(&self.directory)
+            .and_then(|_| fs::write(&partial, data))
+            .and_then(|_| fs::rename(&partial, &path))
+            .map_err(|e| Error::from_io_error(e, &path.to_string_lossy()))?;
+
+        self.write_validators(url, validators)
+    }
+
+    /// Store the validators of the URL.
+    fn write_validators(&self, url: &str, validators: &Validators) -> Result<()> {
+        let path = CachedTransport::validators_path(&self.path(url));
+        let partial = CachedTransport::partial_path(&path);
+
+        let mut stanza = format!("URL: {url}\n");
+        if let Some(etag) = &validators.etag {
+            stanza += &format!("ETag: {etag}\n");
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            stanza += &format!("Last-Modified: {last_modified}\n");
+        }
+
+        fs::write(&partial, stanza)
+            .and_then(|_| fs::rename(&partial, &path))
+            .map_err(|e| Error::from_io_error(e, &path.to_string_lossy()))
+    }
+}
+
+/// ChunkReader storing the content in the cache while it is read.
+///
+/// The content is written to a temporary file, which is moved to the cache
+/// when the content was read completely.
+struct CachingChunks {
+    inner: Box<dyn ChunkReader>,
+    cache: CachedTransport,
+    url: String,
+    validators: Validators,
+    file: Option<fs::File>,
+}
+
+impl CachingChunks {
+    fn new(
+        inner: Box<dyn ChunkReader>,
+        cache: &CachedTransport,
+        url: &str,
+        validators: Validators,
+    ) -> CachingChunks {
+        let partial = CachedTransport::partial_path(&cache.path(url));
+        let file = fs::create_dir_all(&cache.directory).and_then(|_| fs::File::create(&partial));
+
+        let file = match file {
+            Ok(file) => Some(file),
+            Err(e) => {
+                warn!("Caching {url} failed! {e}");
+                None
+            }
+        };
+
+        CachingChunks {
+            inner,
+            cache: cache.clone(),
+            url: url.to_string(),
+            validators,
+            file,
+        }
+    }
+
+    /// Stop caching and remove the temporary file.
+    fn abort(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(CachedTransport::partial_path(&self.cache.path(&self.url)));
+        }
+    }
+
+    /// Move the completely written content to the cache.
+    fn finish(&mut self) {
+        if self.file.take().is_none() {
+            return;
+        }
+
+        let path = self.cache.path(&self.url);
+        let result = fs::rename(CachedTransport::partial_path(&path), &path)
+            .map_err(|e| Error::from_io_error(e, &path.to_string_lossy()))
+            .and_then(|_| self.cache.write_validators(&self.url, &self.validators));
+
+        if let Err(e) = result {
+            warn!("Caching {} failed! {e}", &self.url);
+        }
+    }
+}
+
+#[async_trait]
+impl ChunkReader for CachingChunks {
+    async fn chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        match self.inner.chunk().await {
+            Ok(Some(chunk)) => {
+                if let Some(file) = &mut self.file {
+                    if let Err(e) = file.write_all(&chunk) {
+                        warn!("Caching {} failed! {e}", &self.url);
+                        self.abort();
+                    }
+                }
+                Ok(Some(chunk))
+            }
+            Ok(None) => {
+                self.finish();
+                Ok(None)
+            }
+            Err(e) => {
+                self.abort();
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Drop for CachingChunks {
+    fn drop(&mut self) {
+        // Incomplete content is not cached.
+        self.abort();
+    }
+}
+
+/// Get the path of the cached uncompressed index of the URL.
+fn index_path(directory: &str, url: &str) -> PathBuf {
+    Path::new(directory)
+        .join("indices")
+        .join(CachedTransport::file_name(url))
+}
+
+/// Read the cached uncompressed index, used as base for PDiff updates.
+pub(crate) fn read_index(directory: &str, url: &str) -> Option<String> {
+    fs::read_to_string(index_path(directory, url)).ok()
+}
+
+/// Store the verified uncompressed index, used as base for PDiff updates.
+pub(crate) fn write_index(directory: &str, url: &str, content: &str) {
+    let path = index_path(directory, url);
+
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+
+    let result = fs::create_dir_all(Path::new(directory).join("indices"))
+        .and_then(|_| fs::write(&partial, content))
+        .and_then(|_| fs::rename(&partial, &path));
+
+    if let Err(e) = result {
+        warn!("Caching index {url} failed! {e}");
+    }
+}
+
+#[async_trait]
+impl Transport for CachedTransport {
+    async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        if is_local(url) {
+            return self.inner.download(url).await;
+        }
+
+        let cached = self.read(url);
+
+        if let Some((data, _)) = &cached {
+            if url.contains("/by-hash/") {
+                info!("Using cached by-hash file for {url}.");
+                return Ok(data.clone());
+            }
+        }
+
+        let validators = match &cached {
+            Some((_, validators)) => validators.clone(),
+            None => Validators::default(),
+        };
+
+        match self.inner.download_conditional(url, &validators).await? {
+            Conditional::Modified(data, validators) => {
+                if let Err(e) = self.write(url, &data, &validators) {
+                    warn!("Caching {url} failed! {e}");
+                }
+                Ok(data)
+            }
+            Conditional::NotModified => match cached {
+                Some((data, _)) => {
+                    info!("Using cached file for {url}.");
+                    Ok(data)
+                }
+                None => self.inner.download(url).await,
+            },
+        }
+    }
+
+    async fn get_etag(&self, url: &str) -> Result<String> {
+        self.inner.get_etag(url).await
+    }
+
+    async fn exists(&self, url: &str) -> Result<bool> {
+        self.inner.exists(url).await
+    }
+
+    async fn download_conditional(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> Result<Conditional> {
+        self.inner.download_conditional(url, validators).await
+    }
+
+    async fn open(&self, url: &str) -> Result<Box<dyn ChunkReader>> {
+        if is_local(url) {
+            return self.inner.open(url).await;
+        }
+
+        let validators = self.read_validators(url);
+
+        if validators.is_some() && url.contains("/by-hash/") {
+            info!("Using cached by-hash file for {url}.");
+            return self.open_cached(url);
+        }
+
+        let request = validators.clone().unwrap_or_default();
+        match self.inner.open_conditional(url, &request).await? {
+            ConditionalReader::Modified(reader, validators) => {
+                Ok(Box::new(CachingChunks::new(reader, self, url, validators)))
+            }
+            ConditionalReader::NotModified => match validators {
+                Some(_) => {
+                    info!("Using cached file for {url}.");
+                    self.open_cached(url)
+                }
+                None => self.inner.open(url).await,
+            },
+        }
+    }
+
+    async fn open_conditional(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> Result<ConditionalReader> {
+        self.inner.open_conditional(url, validators).await
+    }
+
+    fn evict(&self, url: &str) {
+        let path = self.path(url);
+        if path.exists() {
+            info!("Removing {url} from the cache.");
+        }
+        let _ = fs::remove_file(CachedTransport::validators_path(&path));
+        let _ = fs::remove_file(&path);
+
+        self.inner.evict(url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{temp_dir, MemoryTransport};
+    use crate::{Distro, Key, Link, LinkHash};
+    use std::collections::HashMap;
+
+    /// Read all chunks of the reader.
+    async fn read_chunks(reader: &mut Box<dyn ChunkReader>) -> Vec<Vec<u8>> {
+        let mut chunks = Vec::new();
+        while let Some(chunk) = reader.chunk().await.unwrap() {
+            chunks.push(chunk);
+        }
+        chunks
+    }
+
+    #[test]
+    fn file_name() {
+        assert_eq!(
+            CachedTransport::file_name(
+                "http://archive.ubuntu.com/ubuntu/dists/jammy/main/binary-amd64/Packages.xz"
+            ),
+            "archive.ubuntu.com_ubuntu_dists_jammy_main_binary-amd64_Packages.xz"
+        );
+        assert_eq!(
+            CachedTransport::file_name("https://example.com/a_b/c%20d"),
+            "example.com_a%5fb_c%2520d"
+        );
+    }
+
+    #[tokio::test]
+    async fn revalidate_cached_file() {
+        let url = "http://example.com/dists/jammy/InRelease";
+        let inner = Arc::new(MemoryTransport::new());
+        inner.set(url, b"Suite: jammy\n");
+
+        let directory = temp_dir("cache-revalidate");
+        let cache = CachedTransport::new(inner.clone(), directory.to_str().unwrap());
+
+        assert_eq!(cache.download(url).await.unwrap(), b"Suite: jammy\n");
+        assert_eq!(inner.downloads(), 1);
+
+        // Unchanged content is served from disk.
+        assert_eq!(cache.download(url).await.unwrap(), b"Suite: jammy\n");
+        assert_eq!(inner.downloads(), 1);
+        assert_eq!(inner.requests(), 2);
+
+        // A new cache using the same directory uses the stored validators.
+        let cache = CachedTransport::new(inner.clone(), directory.to_str().unwrap());
+        assert_eq!(cache.download(url).await.unwrap(), b"Suite: jammy\n");
+        assert_eq!(inner.downloads(), 1);
+
+        // Modified content is downloaded and stored.
+        inner.set(url, b"Suite: jammy-updated\n");
+        assert_eq!(
+            cache.download(url).await.unwrap(),
+            b"Suite: jammy-updated\n"
+        );
+        assert_eq!(inner.downloads(), 2);
+        assert_eq!(
+            fs::read(cache.path(url)).unwrap(),
+            b"Suite: jammy-updated\n"
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn by_hash_without_revalidation() {
+        let url = "http://example.com/dists/jammy/main/binary-amd64/by-hash/SHA256/abcd";
+        let inner = Arc::new(MemoryTransport::new());
+        inner.set(url, b"Package: hello\n");
+
+        let directory = temp_dir("cache-by-hash");
+        let cache = CachedTransport::new(inner.clone(), directory.to_str().unwrap());
+
+        cache.download(url).await.unwrap();
+        cache.download(url).await.unwrap();
+        assert_eq!(inner.requests(), 1);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn open_streams_cached_file() {
+        let url = "http://example.com/dists/jammy/main/binary-amd64/Packages";
+        let inner = Arc::new(MemoryTransport::new().with_chunk_size(4));
+        inner.set(url, b"Package: hello\n");
+
+        let directory = temp_dir("cache-open");
+        let cache = CachedTransport::new(inner.clone(), directory.to_str().unwrap());
+
+        // The content is stored while it is streamed.
+        let mut reader = cache.open(url).await.unwrap();
+        let chunks = read_chunks(&mut reader).await;
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks.concat(), b"Package: hello\n");
+        assert_eq!(fs::read(cache.path(url)).unwrap(), b"Package: hello\n");
+        assert_eq!(inner.downloads(), 1);
+
+        // Unchanged content is streamed from disk.
+        let mut reader = cache.open(url).await.unwrap();
+        assert_eq!(read_chunks(&mut reader).await.concat(), b"Package: hello\n");
+        assert_eq!(inner.downloads(), 1);
+        assert_eq!(inner.requests(), 2);
+
+        // Incompletely read content is not cached.
+        inner.set(url, b"Package: world\n");
+        let mut reader = cache.open(url).await.unwrap();
+        reader.chunk().await.unwrap();
+        drop(reader);
+        assert_eq!(fs::read(cache.path(url)).unwrap(), b"Package: hello\n");
+        assert!(!CachedTransport::partial_path(&cache.path(url)).exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn evict_unverified_content() {
+        let url = "memory://repo/dists/jammy/main/binary-amd64/Packages";
+        let inner = Arc::new(MemoryTransport::new());
+        inner.set(url, b"Package: broken\n");
+
+        let directory = temp_dir("cache-evict");
+        let mut distro = Distro::repo("memory://repo", "jammy", Key::NoSignatureCheck);
+        distro.transport = inner.clone();
+        distro.set_cache(directory.to_str().unwrap());
+        let cache = CachedTransport::new(inner.clone(), directory.to_str().unwrap());
+
+        let content = b"Package: hello\n";
+        let mut hashes = HashMap::new();
+        hashes.insert(
+            LinkHash::Sha256,
+            crate::pdiff::sha256_hex(std::str::from_utf8(content).unwrap()),
+        );
+        let link = Link {
+            url: url.to_string(),
+            size: content.len(),
+            hashes,
+        };
+
+        // The broken content is not kept after the failed verification.
+        assert!(
+            crate::util::download_compressed(&distro, &link, false, &[LinkHash::Sha256])
+                .await
+                .is_err()
+        );
+        assert!(cache.read(url).is_none());
+
+        let mut stream =
+            crate::stream::stream_compressed(&distro, &link, false, &[LinkHash::Sha256])
+                .await
+                .unwrap();
+        while let Some(stanza) = stream.next().await {
+            if stanza.is_err() {
+                break;
+            }
+        }
+        assert!(cache.read(url).is_none());
+
+        // A new download is done if the mirror was fixed.
+        inner.set(url, content);
+        crate::util::download_compressed(&distro, &link, false, &[LinkHash::Sha256])
+            .await
+            .unwrap();
+        assert!(cache.read(url).is_some());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,8 +1,16 @@
 //! Struct Distro and related structs and enums.
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::util::join_url;
-use crate::{Error, Result};
+use crate::cache::CachedTransport;
+use crate::event::Observer;
+use crate::transport::{ClientConfig, HttpTransport, Transport};
+use crate::util::{join_url, local_path};
+use crate::{Compression, Error, LinkHash, Result};
 
 /// The enum Key is used to wrap the apt repository verification key.
 ///
@@ -11,6 +19,16 @@ use crate::{Error, Result};
 ///
 /// A armored key is a _Key::ArmoredKey_.
 /// Armored keys are typically used if a key is provided for download.
+/// The _Key::ArmoredKey_ can also contain the armored key block itself,
+/// e.g. an inline _Signed-By_ key of a deb822 sources file.
+///
+/// Key files may contain several keys, e.g. a keyring.
+///
+/// A directory of keys, like `/etc/apt/trusted.gpg.d/`, is a _Key::KeyDir_.
+/// Files with the extension _.gpg_ are non-armored keys,
+/// files with the extension _.asc_ are armored keys, all other files are ignored.
+///
+/// Several key locations can be combined using a _Key::Keyring_.
 ///
 /// The type _Key::NoSignatureCheck_ can be used to skip the verification of
 /// the distribution index.
@@ -18,6 +36,8 @@ use crate::{Error, Result};
 pub enum Key {
     Key(String),
     ArmoredKey(String),
+    KeyDir(String),
+    Keyring(Vec<Key>),
     NoSignatureCheck,
 }
 
@@ -31,6 +51,114 @@ impl Key {
     pub fn armored_key(url: &str) -> Key {
         Key::ArmoredKey(url.to_string())
     }
+
+    /// Wrap a new key directory location.
+    pub fn key_dir(path: &str) -> Key {
+        Key::KeyDir(path.to_string())
+    }
+
+    /// Combine several key locations.
+    pub fn keyring(keys: Vec<Key>) -> Key {
+        Key::Keyring(keys)
+    }
+}
+
+/// The Freshness configures the freshness check of the _InRelease_ file.
+///
+/// The check protects against replay and freeze attacks,
+/// i.e. a mirror serving an old, but validly signed, _InRelease_ file.
+/// It is similar to the apt options _Check-Valid-Until_, _Check-Date_ and _Max-ValidTime_.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Freshness {
+    /// Reject the Release if the _Valid-Until_ date has passed.
+    pub check_valid_until: bool,
+    /// Reject the Release if the _Date_ is in the future.
+    pub check_date: bool,
+    /// Maximum age of the Release, based on the _Date_ field.
+    ///
+    /// If also a _Valid-Until_ date is given, the earlier date is used.
+    pub max_age: Option<Duration>,
+    /// Tolerated clock difference between the server and the client.
+    pub clock_skew: Duration,
+    /// Time used as current time, if None the system clock is used.
+    pub now: Option<DateTime<Utc>>,
+}
+
+impl Default for Freshness {
+    fn default() -> Freshness {
+        Freshness {
+            check_valid_until: true,
+            check_date: true,
+            max_age: None,
+            clock_skew: Duration::from_secs(10 * 60),
+            now: None,
+        }
+    }
+}
+
+impl Freshness {
+    /// Disable all freshness checks.
+    pub fn disabled() -> Freshness {
+        Freshness {
+            check_valid_until: false,
+            check_date: false,
+            max_age: None,
+            clock_skew: Duration::ZERO,
+            now: None,
+        }
+    }
+}
+
+/// The VerificationPolicy configures which algorithms and keys are accepted.
+///
+/// The default policy is strict and mirrors apt,
+/// i.e. SHA1 signatures, RSA keys smaller than 2048 bits, expired keys
+/// and index files with only SHA1 or MD5 hashes are rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VerificationPolicy {
+    /// Minimum strength of the hash algorithm of the Release signature.
+    ///
+    /// SHA224, SHA384 and SHA3-256 are considered as strong as SHA256,
+    /// and SHA3-512 is considered as strong as SHA512.
+    pub min_signature_hash: LinkHash,
+    /// Minimum size in bits of RSA, DSA and Elgamal signing keys.
+    pub min_key_bits: usize,
+    /// Hashes accepted for verifying the index files.
+    ///
+    /// The strongest accepted hash provided by the Release is used.
+    pub link_hashes: Vec<LinkHash>,
+    /// Accept signatures made by expired keys.
+    ///
+    /// The current time of the _freshness_ configuration is used for the check.
+    pub allow_expired_keys: bool,
+}
+
+impl Default for VerificationPolicy {
+    fn default() -> VerificationPolicy {
+        VerificationPolicy {
+            min_signature_hash: LinkHash::Sha256,
+            min_key_bits: 2048,
+            link_hashes: vec![LinkHash::Sha512, LinkHash::Sha256],
+            allow_expired_keys: false,
+        }
+    }
+}
+
+impl VerificationPolicy {
+    /// Accept all algorithms and keys, e.g. for old repositories.
+    pub fn permissive() -> VerificationPolicy {
+        VerificationPolicy {
+            min_signature_hash: LinkHash::Md5,
+            min_key_bits: 0,
+            link_hashes: vec![
+                LinkHash::Sha512,
+                LinkHash::Sha256,
+                LinkHash::Sha1,
+                LinkHash::Md5,
+            ],
+            allow_expired_keys: true,
+        }
+    }
 }
 
 /// The Distro groups all information required to locate the
@@ -44,12 +172,79 @@ impl Key {
 ///
 /// If a flat repo which makes not use of a subfolder, e.g. Suse Open Build Service,
 /// the path _./_ can be used, like in apt source lists.
+///
+/// The _url_ can also point to a local mirror, either as _file://_ URL
+/// or as plain directory path. Local files are read from the filesystem.
+///
+/// The _mirrors_ are additional base URLs of the same distribution,
+/// which are used in the given order if a download from _url_ fails,
+/// i.e. because of a connection error or a hash mismatch.
+/// Like the _mirror_ method of apt, the _url_ can also be a mirror list file,
+/// e.g. _mirror+file:///etc/apt/mirrors.txt_, which provides one mirror URL per line.
+/// The mirror which served a file is recorded, see [Distro::served_by].
+///
+/// All downloads for this distribution are done using the _transport_.
+/// By default, the [HttpTransport] is used.
+///
+/// The _freshness_ configures the validity check of the _InRelease_ file.
+///
+/// The _policy_ configures the accepted signature and index hash algorithms and keys.
+///
+/// The _cache_ is the directory used by [Distro::set_cache].
+///
+/// The _compressions_ are the candidate compressions of the indices,
+/// in the order of preference.
+/// The indices are selected using the files listed in the _Release_ file.
+/// If _probe_indices_ is true, the existence of the selected index is checked
+/// using the transport, and the next listed compression is used if it doesn't exist.
+///
+/// The _parallel_downloads_ limit the number of concurrent downloads
+/// when fetching multiple indices, e.g. using [crate::Release::get_package_indices].
+///
+/// The _observer_ receives progress and status [crate::Event]s of the downloads and parsing.
+///
+/// The _signed_by_ fingerprints restrict the keys accepted for the signature,
+/// like the _Signed-By_ option of apt. If empty, all keys of _key_ are accepted.
+/// A fingerprint of a primary key also accepts its subkeys,
+/// a fingerprint with the suffix _!_ only accepts exactly this key.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Distro {
     pub url: String,
+    #[serde(default)]
+    pub mirrors: Vec<String>,
     pub name: Option<String>,
     pub path: Option<String>,
     pub key: Key,
+    #[serde(default)]
+    pub signed_by: Vec<String>,
+    #[serde(default)]
+    pub freshness: Freshness,
+    #[serde(default)]
+    pub policy: VerificationPolicy,
+    #[serde(default)]
+    pub cache: Option<String>,
+    #[serde(default = "Compression::default_order")]
+    pub compressions: Vec<Compression>,
+    #[serde(default)]
+    pub probe_indices: bool,
+    #[serde(default = "default_parallel_downloads")]
+    pub parallel_downloads: usize,
+    #[serde(skip)]
+    pub observer: Option<Arc<dyn Observer>>,
+    #[serde(skip, default = "default_transport")]
+    pub transport: Arc<dyn Transport>,
+    #[serde(skip)]
+    served_by: Arc<Mutex<HashMap<String, String>>>,
+}
+
+/// Get the default transport for a Distro.
+fn default_transport() -> Arc<dyn Transport> {
+    Arc::new(HttpTransport::new())
+}
+
+/// Get the default number of concurrent downloads.
+fn default_parallel_downloads() -> usize {
+    4
 }
 
 impl Distro {
@@ -57,9 +252,20 @@ impl Distro {
     pub fn repo(url: &str, name: &str, key: Key) -> Distro {
         Distro {
             url: url.to_string(),
+            mirrors: Vec::new(),
             name: Some(name.to_string()),
             path: None,
             key: key,
+            signed_by: Vec::new(),
+            freshness: Freshness::default(),
+            policy: VerificationPolicy::default(),
+            cache: None,
+            compressions: Compression::default_order(),
+            probe_indices: false,
+            parallel_downloads: default_parallel_downloads(),
+            observer: None,
+            transport: default_transport(),
+            served_by: Arc::default(),
         }
     }
 
@@ -67,24 +273,168 @@ impl Distro {
     pub fn flat_repo(url: &str, directory: &str, key: Key) -> Distro {
         Distro {
             url: url.to_string(),
+            mirrors: Vec::new(),
             name: None,
             path: Some(directory.to_string()),
             key: key,
+            signed_by: Vec::new(),
+            freshness: Freshness::default(),
+            policy: VerificationPolicy::default(),
+            cache: None,
+            compressions: Compression::default_order(),
+            probe_indices: false,
+            parallel_downloads: default_parallel_downloads(),
+            observer: None,
+            transport: default_transport(),
+            served_by: Arc::default(),
         }
     }
 
+    /// Use a [HttpTransport] with the given client configuration.
+    ///
+    /// The transport, and its HTTP client, is shared by all downloads
+    /// of this distribution, i.e. the Release and all indices.
+    pub fn set_client_config(&mut self, config: &ClientConfig) -> Result<()> {
+        self.transport = Arc::new(HttpTransport::with_config(config)?);
+        Ok(())
+    }
+
+    /// Get the base URLs of all mirrors, in the order of preference.
+    ///
+    /// If _url_ is a mirror list, i.e. starts with _mirror+file://_,
+    /// the mirrors of the list file are used instead of _url_.
+    /// Empty lines and comments of the list are ignored,
+    /// and the metadata after the URL is not evaluated.
+    pub fn base_urls(&self) -> Result<Vec<String>> {
+        let mut urls = match self.url.strip_prefix("mirror+") {
+            Some(list) => {
+                let content = fs::read_to_string(local_path(list))
+                    .map_err(|e| Error::from_io_error(e, &self.url))?;
+
+                content
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .filter_map(|line| line.split_whitespace().next())
+                    .map(|url| url.to_string())
+                    .collect()
+            }
+            None => vec![self.url.clone()],
+        };
+
+        urls.extend(self.mirrors.iter().cloned());
+        Ok(urls)
+    }
+
+    /// Get the URLs of the given URL on all mirrors, in the order of preference.
+    ///
+    /// The URL must be based on _url_, e.g. created using [Distro::url].
+    /// Other URLs are returned unchanged.
+    pub fn mirror_urls(&self, url: &str) -> Result<Vec<String>> {
+        Ok(self
+            .mirror_candidates(url)?
+            .into_iter()
+            .map(|(_, url)| url)
+            .collect())
+    }
+
+    /// Get the pairs of mirror base URL and URL of the file on the mirror.
+    pub(crate) fn mirror_candidates(&self, url: &str) -> Result<Vec<(String, String)>> {
+        // The URL must be below the Distro URL, not only share a string prefix,
+        // e.g. http://host/ubuntu-ports is not part of http://host/ubuntu.
+        let path = match url.strip_prefix(&self.url) {
+            Some(path) if path.is_empty() || path.starts_with('/') || self.url.ends_with('/') => {
+                path
+            }
+            _ => return Ok(vec![(url.to_string(), url.to_string())]),
+        };
+
+        Ok(self
+            .base_urls()?
+            .into_iter()
+            .map(|base| {
+                let url = join_url(&base, path);
+                (base, url)
+            })
+            .collect())
+    }
+
+    /// Get the URL of the given URL on the mirror with the given base URL.
+    ///
+    /// Returns the URL unchanged if it is not provided by the mirror.
+    pub(crate) fn mirror_url(&self, url: &str, mirror: &str) -> Result<String> {
+        Ok(self
+            .mirror_candidates(url)?
+            .into_iter()
+            .find(|(base, _)| base == mirror)
+            .map(|(_, url)| url)
+            .unwrap_or_else(|| url.to_string()))
+    }
+
+    /// Get the base URL of the mirror which served the given URL.
+    ///
+    /// The URL is the URL based on _url_, e.g. the URL of a [crate::Link].
+    /// Returns None if the URL was not downloaded.
+    pub fn served_by(&self, url: &str) -> Option<String> {
+        self.served_by
+            .lock()
+            .expect("Mirror log is not poisoned.")
+            .get(url)
+            .cloned()
+    }
+
+    /// Record the mirror which served the given URL.
+    pub(crate) fn record_mirror(&self, url: &str, mirror: &str) {
+        self.served_by
+            .lock()
+            .expect("Mirror log is not poisoned.")
+            .insert(url.to_string(), mirror.to_string());
+    }
+
+    /// Cache all downloads of this distribution in the given directory.
+    ///
+    /// The current transport is wrapped in a [CachedTransport],
+    /// i.e. this method shall be called after configuring the transport.
+    /// The directory is also used to keep the uncompressed indices for PDiff updates.
+    pub fn set_cache(&mut self, directory: &str) {
+        self.cache = Some(directory.to_string());
+        self.transport = Arc::new(CachedTransport::new(self.transport.clone(), directory));
+    }
+
     /// Get the URL of the _InRelease_ index file.
     ///
     /// Returns an error if _name_ and _path_ are _None_.
     pub fn in_release_url(&self) -> Result<String> {
+        self.dist_file_url("InRelease")
+    }
+
+    /// Get the URL of the _Release_ index file.
+    ///
+    /// The _Release_ file is used if no _InRelease_ file is provided.
+    /// Returns an error if _name_ and _path_ are _None_.
+    pub fn release_url(&self) -> Result<String> {
+        self.dist_file_url("Release")
+    }
+
+    /// Get the URL of the detached _Release.gpg_ signature.
+    ///
+    /// Returns an error if _name_ and _path_ are _None_.
+    pub fn release_gpg_url(&self) -> Result<String> {
+        self.dist_file_url("Release.gpg")
+    }
+
+    /// Get the URL of a file in the distribution folder.
+    ///
+    /// Returns an error if _name_ and _path_ are _None_.
+    fn dist_file_url(&self, file: &str) -> Result<String> {
         if let Some(name) = &self.name {
             let url = join_url(&self.url, "dists");
             let url = join_url(&url, &name);
-            let url = join_url(&url, "InRelease");
+            let url = join_url(&url, file);
             Ok(url)
         } else if let Some(path) = &self.path {
             let url = join_url(&self.url, &path);
-            let url = join_url(&url, "InRelease");
+            let url = join_url(&url, file);
             Ok(url)
         } else {
             Err(Error::new("No distro name", crate::ErrorType::DistroFormat))
@@ -118,6 +468,7 @@ impl Distro {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::temp_dir;
 
     #[test]
     fn distro_in_release_url() {
@@ -133,6 +484,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn distro_release_url() {
+        let distro = Distro::repo(
+            "http://archive.ubuntu.com/ubuntu",
+            "jammy",
+            Key::NoSignatureCheck,
+        );
+        assert_eq!(
+            distro.release_url().unwrap(),
+            "http://archive.ubuntu.com/ubuntu/dists/jammy/Release"
+        );
+        assert_eq!(
+            distro.release_gpg_url().unwrap(),
+            "http://archive.ubuntu.com/ubuntu/dists/jammy/Release.gpg"
+        );
+    }
+
     #[test]
     fn flat_distro_in_release_url() {
         let key = Key::key("http://archive.ubuntu.com/ubuntu/key.gpg");
@@ -190,6 +558,28 @@ mod tests {
         assert_eq!(distro.key, Key::NoSignatureCheck);
     }
 
+    #[test]
+    fn local_repo() {
+        let distro = Distro::repo("file:///srv/mirror/ubuntu", "jammy", Key::NoSignatureCheck);
+
+        assert_eq!(
+            distro.in_release_url().unwrap(),
+            "file:///srv/mirror/ubuntu/dists/jammy/InRelease"
+        );
+        assert_eq!(
+            distro.url("main/binary-amd64/Packages.xz", false),
+            "file:///srv/mirror/ubuntu/dists/jammy/main/binary-amd64/Packages.xz"
+        );
+
+        let distro = Distro::flat_repo("/srv/mirror/obs", "./", Key::NoSignatureCheck);
+
+        assert_eq!(
+            distro.in_release_url().unwrap(),
+            "/srv/mirror/obs/InRelease"
+        );
+        assert_eq!(distro.url("pool/a.deb", true), "/srv/mirror/obs/pool/a.deb");
+    }
+
     #[test]
     fn flat_repo_key() {
         let key = Key::armored_key("http://archive.ubuntu.com/ubuntu/key.pub");
@@ -204,4 +594,69 @@ mod tests {
             Key::ArmoredKey("http://archive.ubuntu.com/ubuntu/key.pub".to_string())
         );
     }
+
+    #[test]
+    fn mirror_urls() {
+        let dir = temp_dir("mirrors");
+        let list = dir.join("mirrors.txt");
+        fs::write(
+            &list,
+            "# Mirrors\nhttp://mirror1.example.com/ubuntu\tpriority:1\n\nhttp://mirror2.example.com/ubuntu/\n",
+        )
+        .unwrap();
+
+        let url = format!("mirror+file://{}", list.to_str().unwrap());
+        let mut distro = Distro::repo(&url, "jammy", Key::NoSignatureCheck);
+        distro.mirrors = vec!["file:///srv/mirror".to_string()];
+
+        assert_eq!(
+            distro
+                .mirror_urls(&distro.in_release_url().unwrap())
+                .unwrap(),
+            vec![
+                "http://mirror1.example.com/ubuntu/dists/jammy/InRelease",
+                "http://mirror2.example.com/ubuntu/dists/jammy/InRelease",
+                "file:///srv/mirror/dists/jammy/InRelease",
+            ]
+        );
+
+        // URLs of other locations are not mirrored.
+        assert_eq!(
+            distro
+                .mirror_urls("http://keys.example.com/key.asc")
+                .unwrap(),
+            vec!["http://keys.example.com/key.asc"]
+        );
+
+        let distro = Distro::repo(
+            "http://archive.ubuntu.com/ubuntu",
+            "jammy",
+            Key::NoSignatureCheck,
+        );
+        assert_eq!(
+            distro.mirror_urls(&distro.url("pool/a.deb", true)).unwrap(),
+            vec!["http://archive.ubuntu.com/ubuntu/pool/a.deb"]
+        );
+
+        // URLs sharing only a string prefix with the Distro URL are not mirrored.
+        let mut distro = Distro::repo("http://host/ubuntu", "jammy", Key::NoSignatureCheck);
+        distro.mirrors = vec!["http://mirror.example.com/ubuntu".to_string()];
+        assert_eq!(
+            distro
+                .mirror_urls("http://host/ubuntu-ports/dists/jammy/InRelease")
+                .unwrap(),
+            vec!["http://host/ubuntu-ports/dists/jammy/InRelease"]
+        );
+        assert_eq!(
+            distro
+                .mirror_urls("http://host/ubuntu/dists/jammy/InRelease")
+                .unwrap(),
+            vec![
+                "http://host/ubuntu/dists/jammy/InRelease",
+                "http://mirror.example.com/ubuntu/dists/jammy/InRelease",
+            ]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -20,6 +20,7 @@ pub enum ErrorType {
     Verification,
     DistroFormat,
     UnknownPriority,
+    UnknownMultiArch,
     PackageFormat,
     SourceFormat,
     UnknownVersionRelation,
@@ -27,6 +28,11 @@ pub enum ErrorType {
     InvalidReference,
     ApiUsage,
     Version,
+    Freshness,
+    PDiff,
+    AuthConf,
+    SourcesList,
+    NotFound,
 }
 
 /// Libapt error type.
@@ -45,6 +51,7 @@ impl fmt::Display for Error {
             ErrorType::Verification => "Invalid value",
             ErrorType::DistroFormat => "Invalid distro",
             ErrorType::UnknownPriority => "Unknown priority",
+            ErrorType::UnknownMultiArch => "Unknown Multi-Arch value",
             ErrorType::PackageFormat => "Invalid package metadata",
             ErrorType::SourceFormat => "Invalid source package metadata",
             ErrorType::UnknownVersionRelation => "Unknown package version relation",
@@ -53,6 +60,11 @@ impl fmt::Display for Error {
             ErrorType::ApiUsage => "API usage issue",
             ErrorType::InReleaseStandard => "Debian policy InRelease standard violation",
             ErrorType::Version => "Invalid package version",
+            ErrorType::Freshness => "Release is expired or not yet valid",
+            ErrorType::PDiff => "PDiff update failed",
+            ErrorType::AuthConf => "Invalid auth.conf",
+            ErrorType::SourcesList => "Invalid sources list",
+            ErrorType::NotFound => "Not found",
         };
 
         if let Some(message) = &self.message {
@@ -84,7 +96,11 @@ impl Error {
     }
 
     pub fn from_io_error(error: io::Error, url: &str) -> Error {
-        Error::from_error(&error, ErrorType::Download, &url)
+        let error_type = match error.kind() {
+            io::ErrorKind::NotFound => ErrorType::NotFound,
+            _ => ErrorType::Download,
+        };
+        Error::from_error(&error, error_type, &url)
     }
 
     pub fn from_utf8_error(error: FromUtf8Error, url: &str) -> Error {
@@ -109,4 +125,14 @@ impl Error {
             error_type: error_type,
         }
     }
+
+    /// Get the classification of the error.
+    pub fn error_type(&self) -> &ErrorType {
+        &self.error_type
+    }
+
+    /// Get the error message.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
 }
@@ -0,0 +1,221 @@
+//! Progress and status events of downloads and parsing.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::{Compression, Error, LinkHash};
+
+/// Events reported to the [Observer] of a [crate::Distro].
+///
+/// All events refer to the URL of the file, which is the URL of the [crate::Link]
+/// on the used mirror, also if the file is fetched using the Acquire-By-Hash URL.
+/// The downloads of the InRelease, Release and Release.gpg files and of the keys
+/// are reported using the downloaded URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The download of the URL started.
+    /// The _size_ is the expected size in bytes, if known.
+    DownloadStarted { url: String, size: Option<usize> },
+    /// Data of the URL was received.
+    /// The _received_ bytes are the total number of bytes received so far.
+    DownloadProgress {
+        url: String,
+        received: usize,
+        size: Option<usize>,
+    },
+    /// The download of the URL finished.
+    DownloadFinished { url: String, received: usize },
+    /// The hash of the URL was verified successfully.
+    HashVerified { url: String, hash: LinkHash },
+    /// The hash verification of the URL failed.
+    HashFailed { url: String, error: Error },
+    /// The content of the URL was decompressed.
+    /// The _size_ is the size of the decompressed content in bytes.
+    Decompressed {
+        url: String,
+        compression: Compression,
+        size: usize,
+    },
+    /// The index of the URL was parsed.
+    /// The _stanzas_ are the number of parsed stanzas,
+    /// and the _issues_ are the number of stanzas which failed to parse.
+    Parsed {
+        url: String,
+        stanzas: usize,
+        issues: usize,
+    },
+}
+
+/// An Observer receives the [Event]s of the downloads and parsing of a distribution.
+///
+/// Implement this trait to render progress bars or per-file status,
+/// and assign it to _Distro::observer_.
+/// The events may be reported from different threads,
+/// and concurrently if multiple indices are fetched at once.
+pub trait Observer: fmt::Debug + Send + Sync {
+    /// Handle an event.
+    fn event(&self, event: &Event);
+}
+
+/// Report the event to the observer, if any.
+///
+/// The event is only created if an observer is set.
+pub(crate) fn notify(observer: &Option<Arc<dyn Observer>>, event: impl FnOnce() -> Event) {
+    if let Some(observer) = observer {
+        observer.event(&event());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{temp_dir, TestRepo};
+    use crate::{Architecture, PackageIndex, Release};
+    use std::fs;
+    use std::io::Write;
+    use std::sync::Mutex;
+
+    /// Observer recording all events.
+    #[derive(Debug, Default)]
+    struct RecordingObserver {
+        events: Mutex<Vec<Event>>,
+    }
+
+    impl Observer for RecordingObserver {
+        fn event(&self, event: &Event) {
+            self.events.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn package_index_events() {
+        let packages = b"Package: hello\nVersion: 2.10-2\nMaintainer: Santiago Vila <sanvila@debian.org>\nFilename: pool/main/h/hello/hello_2.10-2_amd64.deb\nSize: 56132\nDescription: example package based on GNU hello\n\nPackage: broken\n";
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(packages).unwrap();
+        let gz = gz.finish().unwrap();
+
+        let root = temp_dir("events");
+        let mut distro = TestRepo::new()
+            .index("main/binary-amd64/Packages.gz", &gz)
+            .build(&root);
+
+        let observer = Arc::new(RecordingObserver::default());
+        distro.observer = Some(observer.clone());
+
+        let release = Release::from_distro(&distro).await.unwrap();
+        let link = release
+            .get_package_index_link("main", &Architecture::Amd64)
+            .await
+            .unwrap();
+        PackageIndex::new(&release, "main", &Architecture::Amd64)
+            .await
+            .unwrap();
+
+        let in_release = distro.in_release_url().unwrap();
+        let url = link.url.clone();
+        let events = observer.events.lock().unwrap();
+        assert_eq!(
+            events[0],
+            Event::DownloadStarted {
+                url: in_release.clone(),
+                size: None
+            }
+        );
+        let in_release_size = fs::metadata(&in_release).unwrap().len() as usize;
+        assert!(events.contains(&Event::DownloadFinished {
+            url: in_release,
+            received: in_release_size
+        }));
+
+        assert!(events.contains(&Event::DownloadStarted {
+            url: url.clone(),
+            size: Some(gz.len())
+        }));
+        assert!(events.contains(&Event::DownloadProgress {
+            url: url.clone(),
+            received: gz.len(),
+            size: Some(gz.len())
+        }));
+        assert!(events.contains(&Event::DownloadFinished {
+            url: url.clone(),
+            received: gz.len()
+        }));
+        assert!(events.contains(&Event::HashVerified {
+            url: url.clone(),
+            hash: crate::LinkHash::Sha256
+        }));
+        assert!(events.contains(&Event::Decompressed {
+            url: url.clone(),
+            compression: Compression::Gzip,
+            size: packages.len()
+        }));
+        assert_eq!(
+            events[events.len() - 1],
+            Event::Parsed {
+                url,
+                stanzas: 2,
+                issues: 1
+            }
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn release_signature_and_key_events() {
+        use crate::test_util::{test_data, MemoryTransport};
+        use crate::{Distro, Key};
+
+        let legacy = test_data("repo/dists/legacy");
+        let release = fs::read(format!("{legacy}/Release")).unwrap();
+        let signature = fs::read(format!("{legacy}/Release.gpg")).unwrap();
+        let key = fs::read(test_data("keys/test.asc")).unwrap();
+
+        let transport = MemoryTransport::new().with_chunk_size(100);
+        transport.set("memory://repo/dists/legacy/Release", &release);
+        transport.set("memory://repo/dists/legacy/Release.gpg", &signature);
+        transport.set("memory://keys/test.asc", &key);
+
+        let mut distro = Distro::repo(
+            "memory://repo",
+            "legacy",
+            Key::armored_key("memory://keys/test.asc"),
+        );
+        distro.transport = Arc::new(transport);
+        let observer = Arc::new(RecordingObserver::default());
+        distro.observer = Some(observer.clone());
+
+        Release::from_distro(&distro).await.unwrap();
+
+        let events = observer.events.lock().unwrap();
+        for (url, data) in [
+            ("memory://repo/dists/legacy/Release", &release),
+            ("memory://repo/dists/legacy/Release.gpg", &signature),
+            ("memory://keys/test.asc", &key),
+        ] {
+            let progress: Vec<usize> = events
+                .iter()
+                .filter_map(|event| match event {
+                    Event::DownloadProgress {
+                        url: u, received, ..
+                    } if u == url => Some(*received),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(progress.len(), data.len().div_ceil(100), "{url}");
+            assert!(events.contains(&Event::DownloadFinished {
+                url: url.to_string(),
+                received: data.len()
+            }));
+        }
+
+        // The missing InRelease file is reported as started, but not finished.
+        assert_eq!(
+            events[0],
+            Event::DownloadStarted {
+                url: "memory://repo/dists/legacy/InRelease".to_string(),
+                size: None
+            }
+        );
+    }
+}
@@ -1,31 +1,60 @@
 #![doc = include_str!("../README.md")]
 
+mod auth;
+mod cache;
 mod distro;
 mod error;
+mod event;
 mod link;
 mod package;
 mod package_index;
 mod package_version;
+mod pdiff;
 mod release;
 mod signature;
 mod source;
 mod source_index;
+mod sources_list;
+mod stream;
+#[cfg(test)]
+mod test_util;
+mod transport;
 mod types;
 mod util;
 mod version;
 
+pub use auth::{find_credentials, parse_auth_conf, read_auth_conf, Authentication, Credentials};
+pub use cache::CachedTransport;
 pub use distro::Distro;
+pub use distro::Freshness;
 pub use distro::Key;
+pub use distro::VerificationPolicy;
 pub use error::{Error, ErrorType, Result};
+pub use event::{Event, Observer};
 pub use link::Link;
 pub use link::LinkHash;
 pub use package::Package;
 pub use package_index::PackageIndex;
-pub use package_version::{PackageVersion, VersionRelation};
-pub use release::Release;
+pub use package_version::{
+    ArchitectureQualifier, ArchitectureRestriction, BuildProfile, Dependency, DependencyGroup,
+    PackageVersion, VersionRelation,
+};
+pub use pdiff::{PDiffEntry, PDiffIndex};
+pub use release::{Release, ReleaseFile};
+pub use signature::SignatureInfo;
 pub use source::Source;
 pub use source_index::SourceIndex;
+pub use sources_list::{
+    format_sources, format_sources_list, parse_sources, parse_sources_list, SourceEntry,
+    SourceType, DEFAULT_KEY_DIR,
+};
+pub use stream::{StanzaStream, Stanzas};
+pub use transport::{
+    ChunkReader, ClientConfig, Conditional, ConditionalReader, HttpTransport, Transport, Validators,
+};
 pub use types::architecture::Architecture;
+pub use types::compression::Compression;
+pub use types::multi_arch::MultiArch;
 pub use types::priority::Priority;
 pub use util::get_etag;
 pub use version::Version;
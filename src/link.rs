@@ -11,7 +11,8 @@ use std::collections::HashMap;
 
 use crate::{source::Source, util::join_url, Distro, Error, ErrorType, Result};
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+/// Hash algorithms of a Link, ordered by strength.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
 pub enum LinkHash {
     Md5,
     Sha1,
@@ -101,6 +102,30 @@ impl Link {
         Ok(url)
     }
 
+    /// Get the Acquire-By-Hash URL of the linked file.
+    ///
+    /// The SHA256 hash is preferred, else the strongest available hash is used.
+    /// Returns None if the link provides no hash.
+    pub fn by_hash_url(&self) -> Option<String> {
+        let hash_types = [
+            (LinkHash::Sha256, "SHA256"),
+            (LinkHash::Sha512, "SHA512"),
+            (LinkHash::Sha1, "SHA1"),
+            (LinkHash::Md5, "MD5Sum"),
+        ];
+
+        let (folder, hash) = hash_types
+            .iter()
+            .find_map(|(hash_type, folder)| self.hashes.get(hash_type).map(|h| (folder, h)))?;
+
+        let base = match self.url.rfind('/') {
+            Some(pos) => &self.url[..pos],
+            None => "",
+        };
+
+        Some(join_url(base, &format!("by-hash/{folder}/{hash}")))
+    }
+
     pub fn add_hash(&mut self, line: &str, hash_type: LinkHash) -> Result<()> {
         let hash = Link::hash_of_line(line)?;
 
@@ -113,3 +138,38 @@ impl Link {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn by_hash_url() {
+        let mut link = Link {
+            url: "http://deb.debian.org/debian/dists/bookworm/main/binary-amd64/Packages.xz"
+                .to_string(),
+            size: 0,
+            hashes: HashMap::new(),
+        };
+        assert_eq!(link.by_hash_url(), None);
+
+        link.hashes.insert(LinkHash::Md5, "1234".to_string());
+        assert_eq!(
+            link.by_hash_url(),
+            Some(
+                "http://deb.debian.org/debian/dists/bookworm/main/binary-amd64/by-hash/MD5Sum/1234"
+                    .to_string()
+            )
+        );
+
+        link.hashes.insert(LinkHash::Sha512, "abcd".to_string());
+        link.hashes.insert(LinkHash::Sha256, "5678".to_string());
+        assert_eq!(
+            link.by_hash_url(),
+            Some(
+                "http://deb.debian.org/debian/dists/bookworm/main/binary-amd64/by-hash/SHA256/5678"
+                    .to_string()
+            )
+        );
+    }
+}
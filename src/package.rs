@@ -12,7 +12,8 @@ use std::cmp::Ordering;
 
 use crate::util::{parse_package_relation, parse_stanza};
 use crate::{
-    Architecture, Distro, Error, ErrorType, Link, PackageVersion, Priority, Result, Version,
+    Architecture, DependencyGroup, Distro, Error, ErrorType, Link, MultiArch, Priority, Result,
+    Version,
 };
 
 /// The Package struct groups all data about a package.
@@ -33,16 +34,18 @@ pub struct Package {
     pub priority: Option<Priority>,
     pub architecture: Option<Architecture>,
     pub essential: Option<bool>,
+    // Multi-Arch field, MultiArch::No if not given
+    pub multi_arch: MultiArch,
     // see https://www.debian.org/doc/debian-policy/ch-relationships.html
-    pub depends: Vec<PackageVersion>,
-    pub pre_depends: Vec<PackageVersion>,
-    pub recommends: Vec<PackageVersion>,
-    pub suggests: Vec<PackageVersion>,
-    pub breaks: Vec<PackageVersion>,
-    pub conflicts: Vec<PackageVersion>,
-    pub provides: Vec<PackageVersion>,
-    pub replaces: Vec<PackageVersion>,
-    pub enhances: Vec<PackageVersion>,
+    pub depends: DependencyGroup,
+    pub pre_depends: DependencyGroup,
+    pub recommends: DependencyGroup,
+    pub suggests: DependencyGroup,
+    pub breaks: DependencyGroup,
+    pub conflicts: DependencyGroup,
+    pub provides: DependencyGroup,
+    pub replaces: DependencyGroup,
+    pub enhances: DependencyGroup,
     pub version: Version,
     pub installed_size: Option<u32>,
     pub link: Link,
@@ -50,7 +53,7 @@ pub struct Package {
     pub description: String,
     pub description_md5: Option<String>,
     pub homepage: Option<String>,
-    pub built_using: Vec<PackageVersion>,
+    pub built_using: DependencyGroup,
     pub issues: Vec<Error>,
 }
 
@@ -77,15 +80,16 @@ impl Package {
             priority: None,
             architecture: None,
             essential: None,
-            depends: Vec::new(),
-            pre_depends: Vec::new(),
-            recommends: Vec::new(),
-            suggests: Vec::new(),
-            breaks: Vec::new(),
-            conflicts: Vec::new(),
-            provides: Vec::new(),
-            replaces: Vec::new(),
-            enhances: Vec::new(),
+            multi_arch: MultiArch::No,
+            depends: DependencyGroup::default(),
+            pre_depends: DependencyGroup::default(),
+            recommends: DependencyGroup::default(),
+            suggests: DependencyGroup::default(),
+            breaks: DependencyGroup::default(),
+            conflicts: DependencyGroup::default(),
+            provides: DependencyGroup::default(),
+            replaces: DependencyGroup::default(),
+            enhances: DependencyGroup::default(),
             version: version,
             installed_size: None,
             link: link,
@@ -93,7 +97,7 @@ impl Package {
             description: description.to_string(),
             description_md5: None,
             homepage: None,
-            built_using: Vec::new(),
+            built_using: DependencyGroup::default(),
             issues: Vec::new(),
         }
     }
@@ -259,6 +263,16 @@ impl Package {
             None => {}
         }
 
+        match kv.get("multi-arch") {
+            Some(multi_arch) => match multi_arch.parse() {
+                Ok(multi_arch) => {
+                    package.multi_arch = multi_arch;
+                }
+                Err(e) => package.issues.push(e),
+            },
+            None => {}
+        }
+
         match kv.get("installed-size") {
             Some(installed_size) => {
                 let is = installed_size.parse::<u32>().map_err(|e| {
@@ -439,6 +453,8 @@ Architecture: arm64
 Version: 5.15.0-1034.43
 Provides: linux-headers, linux-headers-3.0
 Depends: linux-s32-headers-5.15.0-1034, libc6 (>= 2.34), libelf1 (>= 0.142), libssl3 (>= 3.0.0~~alpha1), zlib1g (>= 1:1.2.3.3)
+Recommends: default-mta | mail-transport-agent, libc6
+Multi-Arch: same
 Filename: pool/main/l/linux-s32/linux-headers-5.15.0-1034-s32_5.15.0-1034.43_arm64.deb
 Size: 2794378
 MD5sum: 69c3ccf8a2a6a7f52cf2d795520fa036
@@ -459,35 +475,45 @@ Description-md5: 2ab472dd12387a67ae9ecbe0508146a7
             "Ubuntu Kernel Team <kernel-team@lists.ubuntu.com>"
         );
         assert_eq!(package.architecture, Some(Architecture::Arm64));
+        assert_eq!(package.multi_arch, MultiArch::Same);
         assert_eq!(package.version.epoch, None);
         assert_eq!(package.version.version, "5.15.0");
         assert_eq!(package.version.revision, Some("1034.43".to_string()));
         assert_eq!(package.provides.len(), 2);
-        assert_eq!(package.provides[0].name, "linux-headers");
-        assert_eq!(package.provides[1].name, "linux-headers-3.0");
+        let provides = package.provides.packages();
+        assert_eq!(provides[0].name, "linux-headers");
+        assert_eq!(provides[1].name, "linux-headers-3.0");
         assert_eq!(package.depends.len(), 5);
-        assert_eq!(package.depends[0].name, "linux-s32-headers-5.15.0-1034");
-        assert_eq!(package.depends[1].name, "libc6");
+        let depends = package.depends.packages();
+        assert_eq!(depends[0].name, "linux-s32-headers-5.15.0-1034");
+        assert_eq!(depends[1].name, "libc6");
+        assert_eq!(depends[1].version, Some(Version::from_str("2.34").unwrap()));
+        assert_eq!(depends[2].name, "libelf1");
         assert_eq!(
-            package.depends[1].version,
-            Some(Version::from_str("2.34").unwrap())
-        );
-        assert_eq!(package.depends[2].name, "libelf1");
-        assert_eq!(
-            package.depends[2].version,
+            depends[2].version,
             Some(Version::from_str("0.142").unwrap())
         );
-        assert_eq!(package.depends[3].name, "libssl3");
+        assert_eq!(depends[3].name, "libssl3");
         assert_eq!(
-            package.depends[3].version,
+            depends[3].version,
             Some(Version::from_str("3.0.0~~alpha1").unwrap())
         );
-        assert_eq!(package.depends[4].name, "zlib1g");
+        assert_eq!(depends[4].name, "zlib1g");
         assert_eq!(
-            package.depends[4].version,
+            depends[4].version,
             Some(Version::from_str("1:1.2.3.3").unwrap())
         );
 
+        assert_eq!(package.recommends.len(), 2);
+        let mta = &package.recommends.dependencies[0];
+        assert_eq!(mta.alternatives.len(), 2);
+        assert_eq!(mta.alternatives[0].name, "default-mta");
+        assert_eq!(mta.alternatives[1].name, "mail-transport-agent");
+        assert_eq!(
+            package.recommends.dependencies[1].alternatives[0].name,
+            "libc6"
+        );
+
         let link = package.link;
         assert_eq!(
             link.url,
@@ -3,8 +3,8 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::Error;
 pub use crate::Result;
-use crate::{util::download_compressed, Error};
 use crate::{Architecture, Link, Package, PackageVersion, Release};
 
 /// A PackageIndex is a set of packages for a specific architecture and component.
@@ -50,17 +50,13 @@ impl PackageIndex {
 
     /// Download the package index, verify the hash, and parse the content.
     async fn parse_index(&mut self, link: &Link, release: &Release) -> Result<Vec<Error>> {
-        let content = download_compressed(&link).await?;
+        let results = release
+            .parse_index(link, |stanza| Package::from_stanza(stanza, &release.distro))
+            .await?;
         let mut issues = Vec::new();
 
-        for stanza in content.split("\n\n") {
-            let stanza = stanza.trim();
-
-            if stanza.is_empty() {
-                continue;
-            }
-
-            match Package::from_stanza(stanza, &release.distro) {
+        for result in results {
+            match result {
                 Ok(package) => self.add(package),
                 Err(e) => issues.push(e),
             }
@@ -163,6 +159,47 @@ mod tests {
         assert_eq!(busybox.architecture, Some(Architecture::Amd64));
     }
 
+    #[tokio::test]
+    async fn parse_local_repo() {
+        use crate::test_util::{temp_dir, TestRepo};
+        use std::fs;
+
+        let packages = r#"Package: hello
+Architecture: amd64
+Version: 2.10-2
+Maintainer: Santiago Vila <sanvila@debian.org>
+Filename: pool/main/h/hello/hello_2.10-2_amd64.deb
+Size: 56132
+Description: example package based on GNU hello
+"#;
+
+        let root = temp_dir("local");
+        TestRepo::new()
+            .field("Architectures: amd64")
+            .field("Components: main")
+            .index("main/binary-amd64/Packages", packages.as_bytes())
+            .build(&root);
+
+        let url = format!("file://{}", root.to_str().unwrap());
+        let distro = Distro::repo(&url, "local", Key::NoSignatureCheck);
+
+        let release = Release::from_distro(&distro).await.unwrap();
+        assert_eq!(release.suite, Some("local".to_string()));
+
+        let package_index = PackageIndex::new(&release, "main", &Architecture::Amd64)
+            .await
+            .unwrap();
+        assert_eq!(package_index.package_count(), 1);
+
+        let hello = package_index.get("hello", None).unwrap();
+        assert_eq!(
+            hello.link.url,
+            format!("{url}/pool/main/h/hello/hello_2.10-2_amd64.deb")
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
     #[tokio::test]
     async fn parse_source_index() {
         // Ubuntu Jammy signing key.
@@ -1,7 +1,8 @@
 //! Implementation of package version dependencies.
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
-use crate::{Architecture, Error, ErrorType, Result, Version};
+use crate::{Architecture, Error, ErrorType, MultiArch, Package, Result, Version};
 
 /// A VersionRelation describes the relation between two package versions.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Deserialize, Serialize)]
@@ -49,108 +50,259 @@ impl VersionRelation {
     }
 }
 
+/// The ArchitectureQualifier of a relation, e.g. _:any_ of _python3:any_.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Deserialize, Serialize)]
+pub enum ArchitectureQualifier {
+    /// _:any_, satisfied by packages of any architecture with _Multi-Arch: allowed_.
+    Any,
+    /// _:native_, satisfied by packages of the native architecture, used in build dependencies.
+    Native,
+    /// Explicit architecture, e.g. _:amd64_, used in build dependencies.
+    Architecture(Architecture),
+}
+
+impl FromStr for ArchitectureQualifier {
+    type Err = Error;
+
+    fn from_str(qualifier: &str) -> Result<ArchitectureQualifier> {
+        match qualifier.trim().to_lowercase().as_str() {
+            "any" => Ok(ArchitectureQualifier::Any),
+            "native" => Ok(ArchitectureQualifier::Native),
+            arch => Ok(ArchitectureQualifier::Architecture(Architecture::from_str(
+                arch,
+            )?)),
+        }
+    }
+}
+
+/// An ArchitectureRestriction is an entry of an architecture restriction list,
+/// e.g. _!amd64_ of _[!amd64 !i386]_.
+///
+/// The _architecture_ is an architecture name or wildcard, e.g. _linux-any_.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Deserialize, Serialize)]
+pub struct ArchitectureRestriction {
+    pub negated: bool,
+    pub architecture: String,
+}
+
+/// A BuildProfile is a term of a build profile restriction, e.g. _!nocheck_.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Deserialize, Serialize)]
+pub struct BuildProfile {
+    pub negated: bool,
+    pub name: String,
+}
+
+impl BuildProfile {
+    /// Check if the term is fulfilled for the given active profiles.
+    pub fn is_active(&self, profiles: &[&str]) -> bool {
+        profiles.contains(&self.name.as_str()) != self.negated
+    }
+}
+
 /// A PackageVersion describes a package version dependency.
+///
+/// The _architectures_ are the architecture restriction list, e.g. _[!amd64 !i386]_,
+/// and the _profiles_ are the build profile restriction formula, e.g. _<!nocheck> <cross>_.
+/// Each _<...>_ group of the formula is a list of terms which all must be fulfilled,
+/// and the formula is fulfilled if any of the groups is fulfilled.
+/// Empty lists mean no restriction.
+///
+/// The _architecture_ is the architecture qualifier of the package name, e.g. _:any_.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Deserialize, Serialize)]
 pub struct PackageVersion {
     pub name: String,
-    pub architecture: Option<Architecture>,
+    pub architecture: Option<ArchitectureQualifier>,
     pub version: Option<Version>,
     pub relation: Option<VersionRelation>,
+    pub architectures: Vec<ArchitectureRestriction>,
+    pub profiles: Vec<Vec<BuildProfile>>,
 }
 
 impl PackageVersion {
     /// Create a PackageVersion from it's string representation.
+    ///
+    /// Alternatives, separated by _|_, are returned as separate package versions.
     pub fn from_str(desc: &str) -> Result<Vec<PackageVersion>> {
-        let desc: &str = desc.trim();
-
-        let alternatives: Vec<&str> = desc.split("|").map(|p| p.trim()).collect();
-
-        let result: Result<Vec<Vec<PackageVersion>>> = alternatives
-            .iter()
-            .map(|d| PackageVersion::single_form_str(d))
-            .collect();
-        let result = result?;
-        let result: Vec<PackageVersion> = result.iter().flatten().map(|pv| pv.clone()).collect();
-
-        Ok(result)
+        desc.trim()
+            .split("|")
+            .map(|p| PackageVersion::single_form_str(p.trim()))
+            .collect()
     }
 
-    /// Parse a single package version from string.
-    fn single_form_str(desc: &str) -> Result<Vec<PackageVersion>> {
-        let mut architectures: Vec<Architecture> = Vec::new();
+    /// Parse a single package version from string,
+    /// e.g. _foo:any (>= 1.0) [!amd64] <!nocheck>_.
+    fn single_form_str(relation: &str) -> Result<PackageVersion> {
+        let desc = relation;
 
         // Get the package name.
-        let (name, desc) = if let Some(pos) = desc.find(" ") {
-            let name = &desc[..pos];
-            let desc = &desc[pos + 1..];
-
-            let name = if let Some(pos) = name.find(":") {
-                let arch = &name[pos + 1..];
-                let name = &name[..pos];
-
-                architectures.push(Architecture::from_str(arch)?);
+        let end = desc
+            .find(|c: char| c.is_whitespace() || "([<".contains(c))
+            .unwrap_or(desc.len());
+        let (name, mut desc) = (&desc[..end], desc[end..].trim_start());
+
+        let (name, architecture) = match name.split_once(":") {
+            Some((name, qualifier)) => (name, Some(ArchitectureQualifier::from_str(qualifier)?)),
+            None => (name, None),
+        };
 
-                name
-            } else {
-                name
-            };
+        if name.is_empty() {
+            return Err(Error::new(
+                &format!("Package name of relation {relation} missing!"),
+                ErrorType::PackageFormat,
+            ));
+        }
 
-            (name, desc)
-        } else {
-            (desc.trim(), "")
+        let mut package_version = PackageVersion {
+            name: name.to_string(),
+            architecture,
+            version: None,
+            relation: None,
+            architectures: Vec::new(),
+            profiles: Vec::new(),
         };
 
-        // Check for architecture list
-        if let Some(start) = desc.find("[") {
-            if let Some(end) = desc.find("]") {
-                let archs = &desc[start + 1..end];
-                for arch in archs.split(" ") {
-                    architectures.push(Architecture::from_str(arch)?);
+        while let Some(open) = desc.chars().next() {
+            let close = match open {
+                '(' => ')',
+                '[' => ']',
+                '<' => '>',
+                _ => {
+                    return Err(Error::new(
+                        &format!("Unexpected {desc} in relation of {name}!"),
+                        ErrorType::PackageFormat,
+                    ))
                 }
-            }
-        }
+            };
 
-        // Check for version relation
-        let mut version: Option<Version> = None;
-        let mut relation: Option<VersionRelation> = None;
-        if let Some(start) = desc.find("(") {
-            if let Some(end) = desc.find(")") {
-                let vr = &desc[start + 1..end];
-                if let Some(pos) = vr.find(" ") {
-                    // with relation
-                    relation = Some(VersionRelation::from_str(&vr[..pos])?);
-                    version = Some(Version::from_str(&vr[pos + 1..])?);
-                } else {
-                    // no relation
-                    version = Some(Version::from_str(vr)?);
+            let end = desc.find(close).ok_or_else(|| {
+                Error::new(
+                    &format!("Missing {close} in relation of {name}!"),
+                    ErrorType::PackageFormat,
+                )
+            })?;
+            let content = desc[1..end].trim();
+            desc = desc[end + 1..].trim_start();
+
+            match open {
+                '(' => {
+                    // Relation is optional, e.g. (1.0) instead of (= 1.0).
+                    let pos = content
+                        .find(|c: char| !"<>=".contains(c))
+                        .unwrap_or(content.len());
+                    if pos > 0 {
+                        package_version.relation =
+                            Some(VersionRelation::from_str(&content[..pos])?);
+                    }
+                    package_version.version = Some(Version::from_str(content[pos..].trim())?);
+                }
+                '[' => {
+                    for arch in content.split_whitespace() {
+                        let (negated, arch) = match arch.strip_prefix("!") {
+                            Some(arch) => (true, arch),
+                            None => (false, arch),
+                        };
+                        package_version.architectures.push(ArchitectureRestriction {
+                            negated,
+                            architecture: arch.to_lowercase(),
+                        });
+                    }
+                }
+                _ => {
+                    let terms = content
+                        .split_whitespace()
+                        .map(|term| match term.strip_prefix("!") {
+                            Some(name) => BuildProfile {
+                                negated: true,
+                                name: name.to_string(),
+                            },
+                            None => BuildProfile {
+                                negated: false,
+                                name: term.to_string(),
+                            },
+                        })
+                        .collect();
+                    package_version.profiles.push(terms);
                 }
             }
         }
 
-        // Generate results.
-        let mut package_versions = Vec::new();
+        Ok(package_version)
+    }
 
-        let name = name.to_string();
+    /// Check if the relation applies to the host architecture and the active build profiles.
+    ///
+    /// Like dpkg, the relation applies if the host architecture matches
+    /// any of the non-negated architectures, or none of the negated architectures,
+    /// and if any group of the build profile formula is fulfilled.
+    pub fn applies_to(&self, host: &Architecture, profiles: &[&str]) -> bool {
+        let (negated, positive): (Vec<_>, Vec<_>) =
+            self.architectures.iter().partition(|r| r.negated);
 
-        if architectures.is_empty() {
-            package_versions.push(PackageVersion {
-                name: name,
-                architecture: None,
-                relation: relation,
-                version: version,
-            });
+        let architecture = if negated.iter().any(|r| host.matches(&r.architecture)) {
+            false
+        } else if !positive.is_empty() {
+            positive.iter().any(|r| host.matches(&r.architecture))
         } else {
-            for architecture in architectures {
-                package_versions.push(PackageVersion {
-                    name: name.clone(),
-                    architecture: Some(architecture),
-                    relation: relation.clone(),
-                    version: version.clone(),
-                });
-            }
+            true
+        };
+
+        let profile = self.profiles.is_empty()
+            || self
+                .profiles
+                .iter()
+                .any(|group| group.iter().all(|term| term.is_active(profiles)));
+
+        architecture && profile
+    }
+
+    /// Check if the relation of a package of the _depender_ architecture
+    /// is satisfied by the given package, following the Debian MultiArch rules.
+    ///
+    /// The _native_ architecture is the architecture of the system,
+    /// and packages of architecture _all_ are considered as packages of the native architecture.
+    ///
+    /// - Without qualifier, the package must have the architecture of the depender,
+    ///   or be _Multi-Arch: foreign_.
+    /// - With qualifier _:any_, the package must have the architecture of the depender,
+    ///   or be _Multi-Arch: foreign_ or _Multi-Arch: allowed_.
+    /// - With qualifier _:native_, the package must have the native architecture.
+    /// - With an explicit architecture, the package must have this architecture.
+    ///
+    /// The name and the version relation must also match.
+    /// Virtual packages, i.e. _Provides_, are not considered.
+    pub fn is_satisfied_by(
+        &self,
+        package: &Package,
+        depender: &Architecture,
+        native: &Architecture,
+    ) -> bool {
+        if package.package != self.name {
+            return false;
+        }
+
+        if self.version.is_some() && !self.matches(&package.version) {
+            return false;
         }
 
-        Ok(package_versions)
+        let architecture = match &package.architecture {
+            Some(Architecture::All) | None => native,
+            Some(architecture) => architecture,
+        };
+        let depender = match depender {
+            Architecture::All => native,
+            depender => depender,
+        };
+
+        match &self.architecture {
+            None => architecture == depender || package.multi_arch == MultiArch::Foreign,
+            Some(ArchitectureQualifier::Any) => {
+                architecture == depender
+                    || package.multi_arch == MultiArch::Foreign
+                    || package.multi_arch == MultiArch::Allowed
+            }
+            Some(ArchitectureQualifier::Native) => architecture == native,
+            Some(ArchitectureQualifier::Architecture(qualifier)) => architecture == qualifier,
+        }
     }
 
     /// Check if the given package version matches the requirement.
@@ -172,6 +324,128 @@ impl PackageVersion {
     }
 }
 
+/// A Dependency is a list of alternatives, e.g. _default-mta | mail-transport-agent_.
+///
+/// The dependency is satisfied if any of the alternatives is satisfied.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Deserialize, Serialize)]
+pub struct Dependency {
+    pub alternatives: Vec<PackageVersion>,
+}
+
+impl FromStr for Dependency {
+    type Err = Error;
+
+    /// Parse a dependency with alternatives separated by _|_.
+    fn from_str(desc: &str) -> Result<Dependency> {
+        Ok(Dependency {
+            alternatives: PackageVersion::from_str(desc)?,
+        })
+    }
+}
+
+impl Dependency {
+    /// Check if any alternative is satisfied.
+    ///
+    /// The _satisfied_ function decides if a single package version requirement is fulfilled,
+    /// e.g. by looking up the package in a [crate::PackageIndex].
+    pub fn is_satisfied_by<F: Fn(&PackageVersion) -> bool>(&self, satisfied: F) -> bool {
+        self.alternatives.iter().any(satisfied)
+    }
+    /// Get the alternatives which apply to the host architecture and the active build profiles,
+    /// see [PackageVersion::applies_to].
+    ///
+    /// Returns None if no alternative applies, i.e. the dependency is dropped.
+    pub fn reduce(&self, host: &Architecture, profiles: &[&str]) -> Option<Dependency> {
+        let alternatives: Vec<PackageVersion> = self
+            .alternatives
+            .iter()
+            .filter(|pv| pv.applies_to(host, profiles))
+            .cloned()
+            .collect();
+
+        if alternatives.is_empty() {
+            None
+        } else {
+            Some(Dependency { alternatives })
+        }
+    }
+}
+
+/// A DependencyGroup is the content of a relation field, e.g. _Depends_,
+/// i.e. a conjunction of [Dependency] disjunctions.
+///
+/// For _Depends: default-mta | mail-transport-agent, libc6_ the group
+/// consists of two dependencies, where the first one has two alternatives.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Deserialize, Serialize)]
+pub struct DependencyGroup {
+    pub dependencies: Vec<Dependency>,
+}
+
+impl FromStr for DependencyGroup {
+    type Err = Error;
+
+    /// Parse a relation field with dependencies separated by _,_.
+    fn from_str(desc: &str) -> Result<DependencyGroup> {
+        let dependencies: Result<Vec<Dependency>> = desc
+            .split(",")
+            .map(|d| d.trim())
+            .filter(|d| !d.is_empty())
+            .map(Dependency::from_str)
+            .collect();
+
+        Ok(DependencyGroup {
+            dependencies: dependencies?,
+        })
+    }
+}
+
+impl DependencyGroup {
+    /// Get the number of dependencies.
+    pub fn len(&self) -> usize {
+        self.dependencies.len()
+    }
+
+    /// Check if the group has no dependencies.
+    pub fn is_empty(&self) -> bool {
+        self.dependencies.is_empty()
+    }
+
+    /// Iterate over the dependencies.
+    pub fn iter(&self) -> std::slice::Iter<'_, Dependency> {
+        self.dependencies.iter()
+    }
+
+    /// Get all package versions of all dependencies and alternatives.
+    pub fn packages(&self) -> Vec<&PackageVersion> {
+        self.dependencies
+            .iter()
+            .flat_map(|d| d.alternatives.iter())
+            .collect()
+    }
+
+    /// Get the relations which apply to the host architecture and the active build profiles,
+    /// e.g. the build dependencies for a build of _riscv64_ with the profile _nocheck_.
+    ///
+    /// Alternatives which don't apply are removed,
+    /// and dependencies without applying alternatives are dropped.
+    pub fn reduce(&self, host: &Architecture, profiles: &[&str]) -> DependencyGroup {
+        DependencyGroup {
+            dependencies: self
+                .dependencies
+                .iter()
+                .filter_map(|d| d.reduce(host, profiles))
+                .collect(),
+        }
+    }
+
+    /// Check if all dependencies are satisfied, see [Dependency::is_satisfied_by].
+    pub fn is_satisfied_by<F: Fn(&PackageVersion) -> bool>(&self, satisfied: F) -> bool {
+        self.dependencies
+            .iter()
+            .all(|d| d.is_satisfied_by(&satisfied))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -291,6 +565,169 @@ mod tests {
         assert!(!pv.matches(&c));
     }
 
+    #[test]
+    fn test_dependency_group() {
+        let group =
+            DependencyGroup::from_str("default-mta | mail-transport-agent, libc6 (>= 2.34),")
+                .unwrap();
+        assert_eq!(group.len(), 2);
+        assert_eq!(group.dependencies[0].alternatives.len(), 2);
+        assert_eq!(group.dependencies[0].alternatives[0].name, "default-mta");
+        assert_eq!(
+            group.dependencies[0].alternatives[1].name,
+            "mail-transport-agent"
+        );
+        assert_eq!(group.dependencies[1].alternatives[0].name, "libc6");
+        assert_eq!(group.packages().len(), 3);
+
+        let installed =
+            |pv: &PackageVersion| pv.name == "mail-transport-agent" || pv.name == "libc6";
+        assert!(group.is_satisfied_by(installed));
+        assert!(!group.is_satisfied_by(|pv| pv.name == "libc6"));
+
+        assert!(DependencyGroup::from_str("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_restrictions() {
+        let pv = PackageVersion::from_str(
+            "gcc-multilib:native (>=4.8) [!amd64 !i386] <!nocheck cross> <stage1>",
+        )
+        .unwrap();
+        let pv = &pv[0];
+        assert_eq!(pv.name, "gcc-multilib");
+        assert_eq!(pv.relation, Some(VersionRelation::Larger));
+        assert_eq!(pv.version, Some(Version::from_str("4.8").unwrap()));
+        assert_eq!(
+            pv.architectures,
+            vec![
+                ArchitectureRestriction {
+                    negated: true,
+                    architecture: "amd64".to_string()
+                },
+                ArchitectureRestriction {
+                    negated: true,
+                    architecture: "i386".to_string()
+                },
+            ]
+        );
+        assert_eq!(pv.profiles.len(), 2);
+        assert_eq!(
+            pv.profiles[0],
+            vec![
+                BuildProfile {
+                    negated: true,
+                    name: "nocheck".to_string()
+                },
+                BuildProfile {
+                    negated: false,
+                    name: "cross".to_string()
+                },
+            ]
+        );
+
+        assert!(!pv.applies_to(&Architecture::Amd64, &["stage1"]));
+        assert!(pv.applies_to(&Architecture::Arm64, &["stage1"]));
+        assert!(pv.applies_to(&Architecture::Arm64, &["cross"]));
+        assert!(!pv.applies_to(&Architecture::Arm64, &["cross", "nocheck"]));
+        assert!(!pv.applies_to(&Architecture::Arm64, &[]));
+
+        let pv = PackageVersion::from_str("libseccomp-dev [linux-any]").unwrap();
+        assert!(pv[0].applies_to(&Architecture::Riscv64, &[]));
+        assert!(!pv[0].applies_to(&Architecture::Other("kfreebsd-amd64".to_string()), &[]));
+
+        assert!(PackageVersion::from_str("foo (>= 1.0").is_err());
+        assert!(PackageVersion::from_str("foo [amd64").is_err());
+        assert!(PackageVersion::from_str("foo bar").is_err());
+    }
+
+    #[test]
+    fn test_reduce() {
+        let group = DependencyGroup::from_str(
+            "debhelper-compat (= 13), python3-pytest <!nocheck>, libnuma-dev [amd64 arm64] | libfake-dev [riscv64], valgrind [!riscv64]",
+        )
+        .unwrap();
+
+        let reduced = group.reduce(&Architecture::Riscv64, &["nocheck"]);
+        assert_eq!(reduced.len(), 2);
+        assert_eq!(
+            reduced.dependencies[0].alternatives[0].name,
+            "debhelper-compat"
+        );
+        assert_eq!(reduced.dependencies[1].alternatives.len(), 1);
+        assert_eq!(reduced.dependencies[1].alternatives[0].name, "libfake-dev");
+
+        let reduced = group.reduce(&Architecture::Amd64, &[]);
+        let names: Vec<&str> = reduced
+            .packages()
+            .iter()
+            .map(|pv| pv.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "debhelper-compat",
+                "python3-pytest",
+                "libnuma-dev",
+                "valgrind"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multi_arch() {
+        let package = |name: &str, arch: Architecture, multi_arch: MultiArch| {
+            let mut package =
+                Package::new(name, Version::from_str("1.0-1").unwrap(), 0, "", "", "");
+            package.architecture = Some(arch);
+            package.multi_arch = multi_arch;
+            package
+        };
+        let relation = |desc: &str| PackageVersion::from_str(desc).unwrap().remove(0);
+        let (amd64, arm64) = (&Architecture::Amd64, &Architecture::Arm64);
+
+        assert_eq!(
+            relation("python3:any").architecture,
+            Some(ArchitectureQualifier::Any)
+        );
+        assert_eq!(
+            relation("gcc:native").architecture,
+            Some(ArchitectureQualifier::Native)
+        );
+        assert_eq!(
+            relation("libc6:arm64 (>= 2.34)").architecture,
+            Some(ArchitectureQualifier::Architecture(Architecture::Arm64))
+        );
+
+        // No qualifier: same architecture or Multi-Arch: foreign.
+        let libc = package("libc6", Architecture::Arm64, MultiArch::Same);
+        assert!(relation("libc6 (>= 1.0)").is_satisfied_by(&libc, arm64, amd64));
+        assert!(!relation("libc6 (>= 2.0)").is_satisfied_by(&libc, arm64, amd64));
+        assert!(!relation("libc6").is_satisfied_by(&libc, amd64, amd64));
+        let make = package("make", Architecture::Arm64, MultiArch::Foreign);
+        assert!(relation("make").is_satisfied_by(&make, amd64, amd64));
+        assert!(!relation("libc6").is_satisfied_by(&make, arm64, amd64));
+
+        // Architecture all is the native architecture.
+        let data = package("tzdata", Architecture::All, MultiArch::No);
+        assert!(relation("tzdata").is_satisfied_by(&data, amd64, amd64));
+        assert!(!relation("tzdata").is_satisfied_by(&data, arm64, amd64));
+
+        // :any requires Multi-Arch: allowed or foreign for other architectures.
+        let python = package("python3", Architecture::Amd64, MultiArch::Allowed);
+        assert!(relation("python3:any").is_satisfied_by(&python, arm64, amd64));
+        assert!(!relation("python3").is_satisfied_by(&python, arm64, amd64));
+        let perl = package("perl", Architecture::Amd64, MultiArch::No);
+        assert!(!relation("perl:any").is_satisfied_by(&perl, arm64, amd64));
+        assert!(relation("perl:any").is_satisfied_by(&perl, amd64, amd64));
+
+        // :native and explicit architectures.
+        assert!(relation("perl:native").is_satisfied_by(&perl, arm64, amd64));
+        assert!(!relation("libc6:native").is_satisfied_by(&libc, arm64, amd64));
+        assert!(relation("libc6:arm64").is_satisfied_by(&libc, amd64, amd64));
+        assert!(!relation("perl:arm64").is_satisfied_by(&perl, arm64, amd64));
+    }
+
     #[test]
     fn test_package_version_alternatives() {
         let desc = "linux-s32-headers-5.15.0-1026 | libc6 (>= 2.34) | libelf1 (>= 0.142)";
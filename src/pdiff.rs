@@ -0,0 +1,439 @@
+//! Incremental index updates using PDiffs.
+//!
+//! Debian publishes ed-style patches for the package indices,
+//! described by the _Packages.diff/Index_ file.
+//! See [Debian Wiki PDiffs](https://wiki.debian.org/DebianRepository/Format#A.22Packages.diff.22_Indices).
+
+#[cfg(not(test))]
+use log::info;
+
+#[cfg(test)]
+use std::println as info;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::util::{download_compressed, join_url};
+use crate::{Distro, Error, ErrorType, Link, LinkHash, Result};
+
+/// A file referenced by the PDiff Index.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PDiffEntry {
+    pub sha256: String,
+    pub size: usize,
+    pub name: String,
+}
+
+/// The PDiffIndex groups the data of a _Packages.diff/Index_ file.
+///
+/// The _history_ provides the hash of the index before applying the patch of the same name.
+/// The _patches_ provide the hashes of the uncompressed patches,
+/// and the _downloads_ the hashes of the compressed patch files.
+///
+/// If the patches are _merged_, each patch updates the index directly to the current version,
+/// else the patches must be applied one after another.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PDiffIndex {
+    pub current: Option<(String, usize)>,
+    pub history: Vec<PDiffEntry>,
+    pub patches: Vec<PDiffEntry>,
+    pub downloads: Vec<PDiffEntry>,
+    pub merged: bool,
+}
+
+/// Internal helper as marker for the sections of the PDiff Index file.
+enum PDiffSection {
+    Keywords,
+    History,
+    Patches,
+    Downloads,
+    Ignored,
+}
+
+impl PDiffIndex {
+    /// Parse the content of a _Packages.diff/Index_ file.
+    pub fn parse(content: &str) -> Result<PDiffIndex> {
+        let mut index = PDiffIndex {
+            current: None,
+            history: Vec::new(),
+            patches: Vec::new(),
+            downloads: Vec::new(),
+            merged: false,
+        };
+
+        let mut section = PDiffSection::Keywords;
+
+        for line in content.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            if line.starts_with(' ') {
+                let entry = PDiffIndex::parse_entry(line)?;
+                match section {
+                    PDiffSection::History => index.history.push(entry),
+                    PDiffSection::Patches => index.patches.push(entry),
+                    PDiffSection::Downloads => index.downloads.push(entry),
+                    PDiffSection::Ignored => {}
+                    PDiffSection::Keywords => {
+                        return Err(Error::new(
+                            &format!("Entry without section: {line}"),
+                            ErrorType::PDiff,
+                        ));
+                    }
+                }
+                continue;
+            }
+
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => {
+                    return Err(Error::new(
+                        &format!("Invalid line: {line}"),
+                        ErrorType::PDiff,
+                    ));
+                }
+            };
+
+            section = match key {
+                "SHA256-Current" => {
+                    let parts: Vec<&str> = value.split_whitespace().collect();
+                    if parts.len() != 2 {
+                        return Err(Error::new(
+                            &format!("Invalid current hash: {line}"),
+                            ErrorType::PDiff,
+                        ));
+                    }
+                    let size = PDiffIndex::parse_size(parts[1], line)?;
+                    index.current = Some((parts[0].to_lowercase(), size));
+                    PDiffSection::Keywords
+                }
+                "SHA256-History" => PDiffSection::History,
+                "SHA256-Patches" => PDiffSection::Patches,
+                "SHA256-Download" => PDiffSection::Downloads,
+                "X-Patch-Precedence" => {
+                    index.merged = value == "merged";
+                    PDiffSection::Keywords
+                }
+                _ => PDiffSection::Ignored,
+            };
+        }
+
+        Ok(index)
+    }
+
+    /// Parse a "hash size name" entry line.
+    fn parse_entry(line: &str) -> Result<PDiffEntry> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() != 3 {
+            return Err(Error::new(
+                &format!("Invalid PDiff entry: {line}"),
+                ErrorType::PDiff,
+            ));
+        }
+
+        Ok(PDiffEntry {
+            sha256: parts[0].to_lowercase(),
+            size: PDiffIndex::parse_size(parts[1], line)?,
+            name: parts[2].to_string(),
+        })
+    }
+
+    fn parse_size(size: &str, line: &str) -> Result<usize> {
+        size.parse::<usize>().map_err(|e| {
+            Error::new(
+                &format!("Invalid size in PDiff entry: {e}\n{line}"),
+                ErrorType::PDiff,
+            )
+        })
+    }
+
+    /// Get the names of the patches required to update the index with the given hash.
+    ///
+    /// Returns an error if the index version is not part of the history.
+    pub fn required_patches(&self, sha256: &str) -> Result<Vec<String>> {
+        let sha256 = sha256.to_lowercase();
+
+        let position = match self.history.iter().position(|h| h.sha256 == sha256) {
+            Some(position) => position,
+            None => {
+                return Err(Error::new(
+                    &format!("Index version {sha256} is not part of the PDiff history."),
+                    ErrorType::PDiff,
+                ));
+            }
+        };
+
+        if self.merged {
+            // Merged patches are named T-<current>-F-<history name>.
+            let name = &self.history[position].name;
+            let suffix = format!("-F-{name}");
+            match self.patches.iter().find(|p| p.name.ends_with(&suffix)) {
+                Some(patch) => Ok(vec![patch.name.clone()]),
+                None => Err(Error::new(
+                    &format!("No merged patch for index version {name}."),
+                    ErrorType::PDiff,
+                )),
+            }
+        } else {
+            Ok(self.history[position..]
+                .iter()
+                .map(|h| h.name.clone())
+                .collect())
+        }
+    }
+
+    /// Get the Link of the compressed patch with the given name.
+    ///
+    /// The _base_url_ is the URL of the _Packages.diff_ folder.
+    fn patch_link(&self, base_url: &str, name: &str) -> Result<Link> {
+        let download = self
+            .downloads
+            .iter()
+            .find(|d| d.name == format!("{name}.gz"))
+            .ok_or_else(|| {
+                Error::new(
+                    &format!("No download hash for patch {name}."),
+                    ErrorType::PDiff,
+                )
+            })?;
+
+        let mut hashes = HashMap::new();
+        hashes.insert(LinkHash::Sha256, download.sha256.clone());
+
+        Ok(Link {
+            url: join_url(base_url, &download.name),
+            size: download.size,
+            hashes,
+        })
+    }
+
+    /// Get the hash of the uncompressed patch with the given name.
+    fn patch_hash(&self, name: &str) -> Option<&str> {
+        self.patches
+            .iter()
+            .find(|p| p.name == name)
+            .map(|p| p.sha256.as_str())
+    }
+}
+
+/// Get the SHA256 hash of the content as lower case hex string.
+pub fn sha256_hex(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// Apply an ed-style patch, as created by _diff --ed_, to the content.
+///
+/// The commands _a_, _c_, _d_, and _s/.//_ are supported.
+/// Like created by diff, the commands must be ordered from the end to the start of the file.
+pub fn apply_ed(content: &str, patch: &str) -> Result<String> {
+    let mut lines: Vec<String> = content.lines().map(|l| l.to_string()).collect();
+    let mut current = 0;
+
+    let mut patch_lines = patch.lines();
+
+    while let Some(command) = patch_lines.next() {
+        if command.is_empty() || command == "w" || command == "q" {
+            continue;
+        }
+
+        if command == "s/.//" {
+            if current == 0 || current > lines.len() {
+                return Err(Error::new(
+                    &format!("No current line for command {command}."),
+                    ErrorType::PDiff,
+                ));
+            }
+            lines[current - 1] = lines[current - 1].replacen('.', "", 1);
+            continue;
+        }
+
+        let invalid = || Error::new(&format!("Invalid ed command: {command}"), ErrorType::PDiff);
+
+        let action = command.chars().last().ok_or_else(invalid)?;
+        let range = &command[..(command.len() - 1)];
+        let (start, end) = match range.split_once(',') {
+            Some((start, end)) => (start, end),
+            None => (range, range),
+        };
+        let start: usize = start.parse().map_err(|_| invalid())?;
+        let end: usize = end.parse().map_err(|_| invalid())?;
+
+        if end < start || end > lines.len() || (action != 'a' && start == 0) {
+            return Err(Error::new(
+                &format!("Ed command {command} is out of range."),
+                ErrorType::PDiff,
+            ));
+        }
+
+        let mut text = Vec::new();
+        if action == 'a' || action == 'c' {
+            loop {
+                match patch_lines.next() {
+                    Some(".") => break,
+                    Some(line) => text.push(line.to_string()),
+                    None => {
+                        return Err(Error::new(
+                            &format!("Unterminated text of ed command {command}."),
+                            ErrorType::PDiff,
+                        ));
+                    }
+                }
+            }
+        }
+
+        let count = text.len();
+        match action {
+            'a' => {
+                lines.splice(start..start, text);
+                current = start + count;
+            }
+            'c' => {
+                lines.splice((start - 1)..end, text);
+                current = start - 1 + count;
+            }
+            'd' => {
+                lines.drain((start - 1)..end);
+                current = start - 1;
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    let mut result = lines.join("\n");
+    if !result.is_empty() {
+        result.push('\n');
+    }
+
+    Ok(result)
+}
+
+/// Update the index content _base_ to the version with the hash _target_ using PDiffs.
+///
+/// The _index_link_ is the Link of the _Packages.diff/Index_ file.
+/// The PDiff Index and all patches are verified using their hashes,
+/// and the result is verified using the _target_ hash.
+/// The downloads use the transport and the mirrors of the _distro_.
+pub async fn update_index(
+    distro: &Distro,
+    index_link: &Link,
+    by_hash: bool,
+    hashes: &[LinkHash],
+    base: &str,
+    target: &str,
+) -> Result<String> {
+    let target = target.to_lowercase();
+    let base_hash = sha256_hex(base);
+
+    if base_hash == target {
+        info!("Index {} is up to date.", &index_link.url);
+        return Ok(base.to_string());
+    }
+
+    let content = download_compressed(distro, index_link, by_hash, hashes).await?;
+    let pdiff_index = PDiffIndex::parse(&content)?;
+
+    let base_url = match index_link.url.rfind('/') {
+        Some(pos) => &index_link.url[..pos],
+        None => &index_link.url,
+    };
+
+    let mut content = base.to_string();
+
+    for name in pdiff_index.required_patches(&base_hash)? {
+        info!("Applying PDiff {name} of {base_url}.");
+
+        let link = pdiff_index.patch_link(base_url, &name)?;
+        let patch = download_compressed(distro, &link, false, &[LinkHash::Sha256]).await?;
+
+        if let Some(hash) = pdiff_index.patch_hash(&name) {
+            if sha256_hex(&patch) != hash {
+                return Err(Error::new(
+                    &format!("SHA256 hash verification of patch {} failed!", &link.url),
+                    ErrorType::PDiff,
+                ));
+            }
+        }
+
+        content = apply_ed(&content, &patch)?;
+    }
+
+    if sha256_hex(&content) != target {
+        return Err(Error::new(
+            &format!(
+                "SHA256 hash verification of index patched using {} failed!",
+                &index_link.url
+            ),
+            ErrorType::PDiff,
+        ));
+    }
+
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_ed_commands() {
+        let base = "a\nb\nc\nd\ne\n";
+
+        assert_eq!(apply_ed(base, "5a\nf\n.\n").unwrap(), "a\nb\nc\nd\ne\nf\n");
+        assert_eq!(apply_ed(base, "0a\nz\n.\n").unwrap(), "z\na\nb\nc\nd\ne\n");
+        assert_eq!(apply_ed(base, "2,3d\n").unwrap(), "a\nd\ne\n");
+        assert_eq!(
+            apply_ed(base, "4c\nx\ny\n.\n").unwrap(),
+            "a\nb\nc\nx\ny\ne\n"
+        );
+        // Commands are ordered from the end to the start.
+        assert_eq!(
+            apply_ed(base, "5d\n3,4c\nx\n.\n1a\nq\n.\nw\n").unwrap(),
+            "a\nq\nb\nx\n"
+        );
+        // Text line consisting of a single dot.
+        assert_eq!(
+            apply_ed(base, "1a\n..\n.\ns/.//\n").unwrap(),
+            "a\n.\nb\nc\nd\ne\n"
+        );
+
+        assert!(apply_ed(base, "7d\n").is_err());
+        assert!(apply_ed(base, "1x\n").is_err());
+        assert!(apply_ed(base, "1a\nz\n").is_err());
+    }
+
+    #[test]
+    fn parse_pdiff_index() {
+        let content = "SHA256-Current: AAAA 300\nSHA256-History:\n 1111 100 T-1\n 2222 200 T-2\nSHA256-Patches:\n 3333 10 T-1\n 4444 20 T-2\nSHA256-Download:\n 5555 5 T-1.gz\n 6666 6 T-2.gz\n";
+        let index = PDiffIndex::parse(content).unwrap();
+
+        assert_eq!(index.current, Some(("aaaa".to_string(), 300)));
+        assert_eq!(index.history.len(), 2);
+        assert_eq!(index.patches[1].name, "T-2");
+        assert_eq!(index.downloads[0].size, 5);
+        assert!(!index.merged);
+
+        assert_eq!(index.required_patches("1111").unwrap(), vec!["T-1", "T-2"]);
+        assert_eq!(index.required_patches("2222").unwrap(), vec!["T-2"]);
+        assert!(index.required_patches("9999").is_err());
+
+        let link = index
+            .patch_link("http://example.com/Packages.diff", "T-2")
+            .unwrap();
+        assert_eq!(link.url, "http://example.com/Packages.diff/T-2.gz");
+        assert_eq!(link.hashes[&LinkHash::Sha256], "6666");
+
+        let merged = "X-Patch-Precedence: merged\nSHA256-Current: AAAA 300\nSHA256-History:\n 1111 100 T-1\n 2222 200 T-2\nSHA256-Patches:\n 3333 10 T-3-F-T-1\n 4444 20 T-3-F-T-2\nSHA256-Download:\n 5555 5 T-3-F-T-1.gz\n 6666 6 T-3-F-T-2.gz\n";
+        let index = PDiffIndex::parse(merged).unwrap();
+        assert!(index.merged);
+        assert_eq!(index.required_patches("1111").unwrap(), vec!["T-3-F-T-1"]);
+        assert_eq!(index.required_patches("2222").unwrap(), vec!["T-3-F-T-2"]);
+
+        let link = index
+            .patch_link("http://example.com/Packages.diff", "T-3-F-T-1")
+            .unwrap();
+        assert_eq!(link.url, "http://example.com/Packages.diff/T-3-F-T-1.gz");
+        assert_eq!(link.hashes[&LinkHash::Sha256], "5555");
+    }
+}
@@ -8,17 +8,37 @@ use std::{println as warn, println as info};
 
 use chrono::DateTime;
 use chrono::FixedOffset;
+use chrono::{TimeDelta, Utc};
+use futures::stream::{self, StreamExt};
 use std::collections::HashMap;
+use std::future::Future;
 
 use serde::{Deserialize, Serialize};
 
-use crate::signature::verify_in_release;
-use crate::util::{download, get_etag};
+use crate::cache::{read_index, write_index};
+use crate::event::notify;
+use crate::pdiff::update_index;
+use crate::signature::{verify_in_release, verify_release, SignatureInfo};
+use crate::stream::{stream_compressed, StanzaStream};
+use crate::util::{download_compressed, download_text, mirror_link, on_mirrors};
 use crate::Architecture;
+use crate::Compression;
 use crate::Distro;
+use crate::Event;
+use crate::Freshness;
 use crate::Link;
 use crate::LinkHash;
 use crate::{Error, ErrorType, Result};
+use crate::{PackageIndex, SourceIndex};
+
+/// The ReleaseFile describes which distribution index file was used.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ReleaseFile {
+    /// Inline signed _InRelease_ file.
+    InRelease,
+    /// _Release_ file with detached _Release.gpg_ signature.
+    Release,
+}
 
 /// The Release struct groups all data from the InRelease file.
 ///
@@ -45,6 +65,8 @@ pub struct Release {
     pub changelogs: Option<String>,
     pub snapshots: Option<String>,
     // internal data
+    pub release_file: ReleaseFile,
+    pub signature: Option<SignatureInfo>,
     pub distro: Distro,
     pub issues: Vec<Error>,
 }
@@ -69,23 +91,54 @@ impl Release {
             signed_by: Vec::new(),
             changelogs: None,
             snapshots: None,
+            release_file: ReleaseFile::InRelease,
+            signature: None,
             distro: distro.clone(),
             issues: Vec::new(),
         }
     }
 
     /// Download and parse the InRelease file of the given Distro.
+    ///
+    /// If the distribution provides no InRelease file,
+    /// the Release file and the detached Release.gpg signature are used.
+    /// Other errors of the InRelease download are returned.
     pub async fn from_distro(distro: &Distro) -> Result<Release> {
         // Get URL content.
         let url = distro.in_release_url()?;
-        let content = download(&url).await?;
+        let (content, signature, release_file) = match on_mirrors(distro, &url, |url| async move {
+            download_text(distro, &url).await
+        })
+        .await
+        {
+            Ok(content) => {
+                // Verify signature.
+                let (content, signature) = verify_in_release(content, distro).await?;
+                (content, signature, ReleaseFile::InRelease)
+            }
+            Err(e) if e.error_type() == &ErrorType::NotFound => {
+                info!(
+                    "No InRelease file for distro {:?}, trying Release. {e}",
+                    &distro.name
+                );
+                let url = distro.release_url()?;
+                let content = on_mirrors(distro, &url, |url| async move {
+                    download_text(distro, &url).await
+                })
+                .await?;
 
-        // Verify signature.
-        let content = verify_in_release(content, distro).await?;
+                // Verify detached signature.
+                let (content, signature) = verify_release(content, distro).await?;
+                (content, signature, ReleaseFile::Release)
+            }
+            Err(e) => return Err(e),
+        };
 
         // Parse content.
         let mut section = ReleaseSection::Keywords;
         let mut release = Release::new(distro);
+        release.release_file = release_file;
+        release.signature = signature;
 
         for line in content.lines() {
             if line.trim().is_empty() {
@@ -244,9 +297,80 @@ impl Release {
             }
         }
 
+        release.check_freshness(&distro.freshness)?;
+
         Ok(release)
     }
 
+    /// Check that the Release is neither expired nor not yet valid.
+    ///
+    /// This check is done by [Release::from_distro] using the freshness
+    /// configuration of the Distro. An error of type _ErrorType::Freshness_
+    /// is returned if the check fails.
+    pub fn check_freshness(&self, freshness: &Freshness) -> Result<()> {
+        let now = freshness.now.unwrap_or_else(Utc::now);
+        let clock_skew =
+            TimeDelta::from_std(freshness.clock_skew).unwrap_or(TimeDelta::max_value());
+
+        if freshness.check_date {
+            if let Some(date) = &self.date {
+                if date.to_utc()
+                    > now
+                        .checked_add_signed(clock_skew)
+                        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+                {
+                    return Err(Error::new(
+                        &format!("Release date {date} is in the future, current time is {now}."),
+                        ErrorType::Freshness,
+                    ));
+                }
+            }
+        }
+
+        let mut valid_until = if freshness.check_valid_until {
+            self.valid_until.map(|v| v.to_utc())
+        } else {
+            None
+        };
+
+        if let Some(max_age) = freshness.max_age {
+            let date = match &self.date {
+                Some(date) => date.to_utc(),
+                None => {
+                    return Err(Error::new(
+                        "Release provides no date, but a maximum age is configured.",
+                        ErrorType::Freshness,
+                    ));
+                }
+            };
+
+            let max_age = TimeDelta::from_std(max_age).unwrap_or(TimeDelta::max_value());
+            let expires = date
+                .checked_add_signed(max_age)
+                .unwrap_or(DateTime::<Utc>::MAX_UTC);
+
+            valid_until = match valid_until {
+                Some(valid_until) if valid_until < expires => Some(valid_until),
+                _ => Some(expires),
+            };
+        }
+
+        if let Some(valid_until) = valid_until {
+            if valid_until
+                .checked_add_signed(clock_skew)
+                .unwrap_or(DateTime::<Utc>::MAX_UTC)
+                < now
+            {
+                return Err(Error::new(
+                    &format!("Release expired at {valid_until}, current time is {now}."),
+                    ErrorType::Freshness,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn check_compliance(&self) -> Result<()> {
         if self.components.is_empty() {
             return Err(Error::new(
@@ -289,25 +413,299 @@ impl Release {
         Ok(())
     }
 
-    pub async fn get_package_links(&self) -> Vec<(String, Architecture, Link)> {
-        let mut components = Vec::new();
+    /// Download, verify and decompress the index file referenced by the given Link.
+    ///
+    /// If the distribution supports Acquire-By-Hash, the index is fetched
+    /// using the by-hash URL, and the canonical URL is only used as fallback.
+    /// The index is verified using the hashes accepted by the policy of the Distro.
+    ///
+    /// If the Distro has a cache, and the Release provides a PDiff Index for the file,
+    /// the cached index is updated using the PDiffs, and verified using
+    /// the SHA256 hash of the uncompressed index from the Release.
+    /// If this fails, the full index is downloaded.
+    pub async fn download_index(&self, link: &Link) -> Result<String> {
+        let extension = Compression::from_extension(&link.url).extension();
+        let plain_url = &link.url[..(link.url.len() - extension.len())];
+
+        let cache = match (
+            &self.distro.cache,
+            self.links.get(&format!("{plain_url}.diff/Index")),
+        ) {
+            (Some(cache), Some(pdiff_link)) => {
+                if let Some(content) = self.update_index(cache, plain_url, pdiff_link).await {
+                    return Ok(content);
+                }
+                Some(cache)
+            }
+            _ => None,
+        };
+
+        let content = download_compressed(
+            &self.distro,
+            link,
+            self.acquire_by_hash,
+            &self.distro.policy.link_hashes,
+        )
+        .await?;
+
+        if let Some(cache) = cache {
+            write_index(cache, plain_url, &content);
+        }
 
+        Ok(content)
+    }
+
+    /// Download the index file of the given Link and stream the stanzas.
+    ///
+    /// The index is verified and decompressed while the stanzas are consumed,
+    /// see [StanzaStream] for the handling of verification errors.
+    /// The next mirror is only used if the index can't be opened,
+    /// since the stanzas are already consumed when the verification fails.
+    /// For the same reason, a stream using the Acquire-By-Hash URL ends with
+    /// the verification error, and the caller must retry with _acquire_by_hash_
+    /// disabled, see [StanzaStream::is_by_hash].
+    /// If a cache directory is configured and the index provides PDiffs,
+    /// the index is updated as in [Release::download_index],
+    /// since the PDiffs are applied to the complete content.
+    pub async fn stream_index(&self, link: &Link) -> Result<StanzaStream> {
+        if self.has_pdiffs(link) {
+            let content = self.download_index(link).await?;
+            return Ok(StanzaStream::from_text(&content));
+        }
+
+        on_mirrors(&self.distro, &link.url, |url| async move {
+            stream_compressed(
+                &self.distro,
+                &mirror_link(link, url),
+                self.acquire_by_hash,
+                &self.distro.policy.link_hashes,
+            )
+            .await
+        })
+        .await
+    }
+
+    /// Download the index file of the given Link and parse the stanzas.
+    ///
+    /// The stanzas are parsed while the index is streamed, see [Release::stream_index].
+    /// If the download or the verification fails, the index is parsed again,
+    /// using the canonical URL if the Acquire-By-Hash URL was used, and else using the next mirror.
+    /// The parse results are reported per stanza.
+    pub(crate) async fn parse_index<T, F>(&self, link: &Link, parse: F) -> Result<Vec<Result<T>>>
+    where
+        F: Fn(&str) -> Result<T>,
+    {
+        if self.has_pdiffs(link) {
+            let content = self.download_index(link).await?;
+            let stream = StanzaStream::from_text(&content);
+            return self.parse_stream(stream, &link.url, &parse).await;
+        }
+
+        let parse = &parse;
+        on_mirrors(&self.distro, &link.url, |url| async move {
+            let link = mirror_link(link, url);
+            let stream = stream_compressed(
+                &self.distro,
+                &link,
+                self.acquire_by_hash,
+                &self.distro.policy.link_hashes,
+            )
+            .await?;
+
+            if !stream.is_by_hash() {
+                return self.parse_stream(stream, &link.url, parse).await;
+            }
+
+            match self.parse_stream(stream, &link.url, parse).await {
+                Ok(results) => Ok(results),
+                Err(e) => {
+                    info!(
+                        "By-hash download of {} failed, using canonical URL. {e}",
+                        &link.url
+                    );
+                    let stream = stream_compressed(
+                        &self.distro,
+                        &link,
+                        false,
+                        &self.distro.policy.link_hashes,
+                    )
+                    .await?;
+                    self.parse_stream(stream, &link.url, parse).await
+                }
+            }
+        })
+        .await
+    }
+
+    /// Parse all stanzas of the stream, and report the result to the observer.
+    async fn parse_stream<T, F>(
+        &self,
+        mut stream: StanzaStream,
+        url: &str,
+        parse: &F,
+    ) -> Result<Vec<Result<T>>>
+    where
+        F: Fn(&str) -> Result<T>,
+    {
+        let mut results = Vec::new();
+        while let Some(stanza) = stream.next().await {
+            results.push(parse(&stanza?));
+        }
+
+        notify(&self.distro.observer, || Event::Parsed {
+            url: url.to_string(),
+            stanzas: results.len(),
+            issues: results.iter().filter(|r| r.is_err()).count(),
+        });
+
+        Ok(results)
+    }
+
+    /// Check if the index of the Link is updated using PDiffs.
+    fn has_pdiffs(&self, link: &Link) -> bool {
+        let extension = Compression::from_extension(&link.url).extension();
+        let plain_url = &link.url[..(link.url.len() - extension.len())];
+
+        self.distro.cache.is_some() && self.links.contains_key(&format!("{plain_url}.diff/Index"))
+    }
+
+    /// Update the cached index using PDiffs.
+    ///
+    /// Returns None if no cached index exists or the update failed.
+    async fn update_index(
+        &self,
+        cache: &str,
+        plain_url: &str,
+        pdiff_link: &Link,
+    ) -> Option<String> {
+        let base = read_index(cache, plain_url)?;
+
+        let target = match self.links.get(plain_url) {
+            Some(link) => link.hashes.get(&LinkHash::Sha256)?,
+            None => {
+                info!("No hash for {plain_url}, PDiffs can't be used.");
+                return None;
+            }
+        };
+
+        match update_index(
+            &self.distro,
+            pdiff_link,
+            self.acquire_by_hash,
+            &self.distro.policy.link_hashes,
+            &base,
+            target,
+        )
+        .await
+        {
+            Ok(content) => {
+                write_index(cache, plain_url, &content);
+                Some(content)
+            }
+            Err(e) => {
+                warn!("PDiff update of {plain_url} failed, downloading full index. {e}");
+                None
+            }
+        }
+    }
+
+    /// Get the package index Links of all components and architectures of the Release.
+    pub async fn get_package_links(&self) -> Vec<(String, Architecture, Link)> {
+        let mut selection = Vec::new();
         for architecture in &self.architectures {
             for component in &self.components {
-                let link = match self.get_package_index_link(component, architecture).await {
-                    Ok(link) => link,
-                    Err(_) => {
-                        info!("No link for component {component} and architecture {architecture}. Skipping.");
-                        continue;
-                    }
-                };
-                components.push((component.to_string(), architecture.clone(), link));
+                selection.push((component, architecture));
+            }
+        }
+
+        let links =
+            self.run_concurrently(selection.into_iter().map(
+                |(component, architecture)| async move {
+                    (
+                        component,
+                        architecture,
+                        self.get_package_index_link(component, architecture).await,
+                    )
+                },
+            ))
+            .await;
+
+        let mut components = Vec::new();
+        for (component, architecture, link) in links {
+            match link {
+                Ok(link) => components.push((component.to_string(), architecture.clone(), link)),
+                Err(_) => {
+                    info!("No link for component {component} and architecture {architecture}. Skipping.");
+                }
             }
         }
 
         components
     }
 
+    /// Download and parse the package indices of the given components and architectures.
+    ///
+    /// The indices are fetched concurrently, using at most _Distro::parallel_downloads_
+    /// downloads at the same time. The result is reported for each index,
+    /// in the order of the components and architectures.
+    pub async fn get_package_indices(
+        &self,
+        components: &[String],
+        architectures: &[Architecture],
+    ) -> Vec<(String, Architecture, Result<PackageIndex>)> {
+        let mut selection = Vec::new();
+        for architecture in architectures {
+            for component in components {
+                selection.push((component, architecture));
+            }
+        }
+
+        self.run_concurrently(
+            selection
+                .into_iter()
+                .map(|(component, architecture)| async move {
+                    let index = PackageIndex::new(self, component, architecture).await;
+                    (component.clone(), architecture.clone(), index)
+                }),
+        )
+        .await
+    }
+
+    /// Download and parse the source indices of the given components.
+    ///
+    /// The indices are fetched concurrently, like for [Release::get_package_indices].
+    pub async fn get_source_indices(
+        &self,
+        components: &[String],
+    ) -> Vec<(String, Result<SourceIndex>)> {
+        self.run_concurrently(components.iter().map(|component| async move {
+            (component.clone(), SourceIndex::new(self, component).await)
+        }))
+        .await
+    }
+
+    /// Run the futures with the parallelism limit of the distribution.
+    ///
+    /// The results are returned in the order of the futures.
+    async fn run_concurrently<T, F>(&self, futures: impl Iterator<Item = F>) -> Vec<T>
+    where
+        F: Future<Output = T>,
+    {
+        let mut results: Vec<(usize, T)> = stream::iter(futures.enumerate())
+            .map(|(i, future)| async move { (i, future.await) })
+            .buffer_unordered(self.distro.parallel_downloads.max(1))
+            .collect()
+            .await;
+
+        results.sort_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Get the Link of the package index for the given component and architecture.
+    ///
+    /// The index is selected using the links of the Release,
+    /// preferring the compressions in the order of _Distro::compressions_.
+    /// The transport is only used if _Distro::probe_indices_ is set.
     pub async fn get_package_index_link(
         &self,
         component: &str,
@@ -322,28 +720,37 @@ impl Release {
 
         let index_url = self.distro.url(&index_url, false);
 
-        // Supported compression extensions, try form best to no compression
-        let extensions = vec![".xz", ".gz", ""];
-
-        for ext in extensions {
+        // Supported compressions, in the order of preference of the distro.
+        for compression in &self.distro.compressions {
             // Build URL for compressed index.
-            let package_index = index_url.clone() + ext;
+            let package_index = index_url.clone() + compression.extension();
 
             // Find link in release.
             // The link is mandatory to get the hash sums for verification.
-            match self.links.get(&package_index) {
-                Some(link) => {
-                    match get_etag(&link.url).await {
-                        Ok(_) => return Ok(link.clone()), // Index file exists.
-                        Err(_) => {
-                            info!("No etag for {package_index}, trying next link.");
-                            continue;
-                        }
-                    }
-                }
-                None => {
-                    info!("Index {package_index} not found.");
+            let link = match self.links.get(&package_index) {
+                Some(link) => link,
+                None => continue,
+            };
+
+            if !self.distro.probe_indices {
+                return Ok(link.clone());
+            }
+
+            let exists = on_mirrors(&self.distro, &link.url, |url| async move {
+                match self.distro.transport.exists(&url).await {
+                    Ok(true) => Ok(()),
+                    Ok(false) => Err(Error::new(
+                        &format!("Index {url} doesn't exist!"),
+                        ErrorType::Download,
+                    )),
+                    Err(e) => Err(e),
                 }
+            })
+            .await;
+
+            match exists {
+                Ok(()) => return Ok(link.clone()),
+                Err(e) => info!("Probing {package_index} failed, trying next link. {e}"),
             }
         }
 
@@ -366,7 +773,11 @@ enum ReleaseSection {
 
 #[cfg(test)]
 mod tests {
-    use crate::{Distro, Key, Release};
+    use super::ReleaseFile;
+    use crate::test_util::{temp_dir, test_data, test_fingerprint, TestRepo};
+    use crate::{Distro, ErrorType, Freshness, Key, Release};
+    use chrono::DateTime;
+    use std::time::Duration;
 
     #[tokio::test]
     async fn parse_ubuntu_jammy_release_file() {
@@ -417,6 +828,593 @@ mod tests {
         release.check_compliance().unwrap();
     }
 
+    #[tokio::test]
+    async fn parse_local_in_release() {
+        let key = Key::armored_key(&test_data("keys/test.asc"));
+        let distro = Distro::repo(&test_data("repo"), "stable", key);
+
+        let release = Release::from_distro(&distro).await.unwrap();
+
+        assert_eq!(release.release_file, ReleaseFile::InRelease);
+        assert_eq!(release.suite, Some("stable".to_string()), "Suite");
+        release.check_compliance().unwrap();
+
+        let fingerprint = test_fingerprint("test");
+        let signature = release.signature.unwrap();
+        assert_eq!(signature.fingerprint, fingerprint);
+        assert_eq!(signature.primary_fingerprint, fingerprint);
+        assert_eq!(signature.key_id, fingerprint[24..]);
+        assert_eq!(signature.hash_algorithm, "SHA256");
+        assert!(signature.created.is_some());
+        assert!(!signature.subkey);
+    }
+
+    #[tokio::test]
+    async fn signature_info_subkey() {
+        let key = Key::armored_key(&test_data("keys/subkey.asc"));
+        let distro = Distro::repo(&test_data("repo"), "subkey", key);
+
+        let release = Release::from_distro(&distro).await.unwrap();
+
+        let signature = release.signature.unwrap();
+        assert_eq!(signature.fingerprint, test_fingerprint("subkey-sign"));
+        assert_eq!(signature.primary_fingerprint, test_fingerprint("subkey"));
+        assert!(signature.subkey);
+
+        // No signature info without signature check.
+        let distro = Distro::repo(&test_data("repo"), "stable", Key::NoSignatureCheck);
+        let release = Release::from_distro(&distro).await.unwrap();
+        assert_eq!(release.signature, None);
+    }
+
+    #[tokio::test]
+    async fn parse_detached_release() {
+        let key = Key::key(&test_data("keys/test.gpg"));
+        let distro = Distro::repo(&test_data("repo"), "legacy", key);
+
+        let release = Release::from_distro(&distro).await.unwrap();
+
+        assert_eq!(release.release_file, ReleaseFile::Release);
+        assert_eq!(release.suite, Some("legacy".to_string()), "Suite");
+        assert_eq!(release.links.len(), 2);
+        release.check_compliance().unwrap();
+
+        let signature = release.signature.unwrap();
+        assert_eq!(signature.fingerprint, test_fingerprint("test"));
+    }
+
+    #[tokio::test]
+    async fn detached_signature_from_release_mirror() {
+        use crate::test_util::MemoryTransport;
+        use std::fs;
+        use std::sync::Arc;
+
+        let legacy = test_data("repo/dists/legacy");
+        let release = fs::read(format!("{legacy}/Release")).unwrap();
+        let signature = fs::read(format!("{legacy}/Release.gpg")).unwrap();
+
+        // The first mirror lost its Release file, but still has a broken signature.
+        let transport = MemoryTransport::new();
+        transport.set("memory://first/dists/legacy/Release.gpg", b"broken");
+        transport.set("memory://second/dists/legacy/Release", &release);
+        transport.set("memory://second/dists/legacy/Release.gpg", &signature);
+
+        let key = Key::key(&test_data("keys/test.gpg"));
+        let mut distro = Distro::repo("memory://first", "legacy", key);
+        distro.mirrors = vec!["memory://second".to_string()];
+        distro.transport = Arc::new(transport);
+
+        let release = Release::from_distro(&distro).await.unwrap();
+        assert_eq!(release.release_file, ReleaseFile::Release);
+        assert_eq!(
+            release.signature.unwrap().fingerprint,
+            test_fingerprint("test")
+        );
+        assert_eq!(
+            distro.served_by(&distro.release_gpg_url().unwrap()),
+            Some("memory://second".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn in_release_error_without_fallback() {
+        use std::fs;
+
+        let root = temp_dir("in-release-error");
+        let distro = TestRepo::new().build(&root);
+
+        // A readable Release file must not hide a failing InRelease download.
+        let dists = root.join("dists/local");
+        fs::rename(dists.join("InRelease"), dists.join("Release")).unwrap();
+        fs::create_dir(dists.join("InRelease")).unwrap();
+
+        let error = Release::from_distro(&distro).await.unwrap_err();
+        assert_eq!(error.error_type(), &ErrorType::Download);
+
+        // A missing InRelease file falls back to the Release file.
+        fs::remove_dir(dists.join("InRelease")).unwrap();
+        let release = Release::from_distro(&distro).await.unwrap();
+        assert_eq!(release.release_file, ReleaseFile::Release);
+    }
+
+    #[tokio::test]
+    async fn download_index_by_hash() {
+        use sha2::{Digest, Sha256};
+        use std::fs;
+
+        let packages = "Package: hello\n";
+        let hash = format!("{:x}", Sha256::digest(packages.as_bytes()));
+
+        let root = temp_dir("by-hash");
+        let distro = TestRepo::new()
+            .field("Acquire-By-Hash: yes")
+            .listed("main/binary-amd64/Packages", packages.as_bytes())
+            .file(
+                &format!("main/binary-amd64/by-hash/SHA256/{hash}"),
+                packages.as_bytes(),
+            )
+            // Canonical index was already updated by the mirror.
+            .file("main/binary-amd64/Packages", b"Package: hello-updated\n")
+            .build(&root);
+        let index_dir = root.join("dists/local/main/binary-amd64");
+
+        let mut release = Release::from_distro(&distro).await.unwrap();
+        assert!(release.acquire_by_hash);
+
+        let link = release.links.values().next().unwrap().clone();
+        assert_eq!(release.download_index(&link).await.unwrap(), packages);
+
+        // Canonical URL is not used without Acquire-By-Hash.
+        release.acquire_by_hash = false;
+        assert!(release.download_index(&link).await.is_err());
+
+        // Canonical URL is used as fallback.
+        fs::write(index_dir.join("Packages"), packages).unwrap();
+        fs::remove_dir_all(index_dir.join("by-hash")).unwrap();
+        release.acquire_by_hash = true;
+        assert_eq!(release.download_index(&link).await.unwrap(), packages);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stream_index_by_hash_mismatch() {
+        use crate::{Architecture, PackageIndex};
+        use sha2::{Digest, Sha256};
+        use std::fs;
+
+        let packages = "Package: hello\nVersion: 2.10-2\nMaintainer: Santiago Vila <sanvila@debian.org>\nFilename: pool/main/h/hello/hello_2.10-2_amd64.deb\nSize: 56132\nDescription: example package based on GNU hello\n";
+        let hash = format!("{:x}", Sha256::digest(packages.as_bytes()));
+
+        // The by-hash file of the mirror is broken.
+        let root = temp_dir("stream-by-hash");
+        let distro = TestRepo::new()
+            .field("Acquire-By-Hash: yes")
+            .index("main/binary-amd64/Packages", packages.as_bytes())
+            .file(
+                &format!("main/binary-amd64/by-hash/SHA256/{hash}"),
+                b"Package: broken\n",
+            )
+            .build(&root);
+
+        let release = Release::from_distro(&distro).await.unwrap();
+        let link = release
+            .get_package_index_link("main", &Architecture::Amd64)
+            .await
+            .unwrap();
+
+        // The stream can't fall back after yielding stanzas.
+        let mut stream = release.stream_index(&link).await.unwrap();
+        assert!(stream.is_by_hash());
+        let mut failed = false;
+        while let Some(stanza) = stream.next().await {
+            failed |= stanza.is_err();
+        }
+        assert!(failed);
+
+        // Parsing the index falls back to the canonical URL.
+        let index = PackageIndex::new(&release, "main", &Architecture::Amd64)
+            .await
+            .unwrap();
+        assert!(index.get("hello", None).is_some());
+        assert!(index.get("broken", None).is_none());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mirror_failover() {
+        use crate::{Architecture, PackageIndex};
+        use std::fs;
+
+        let packages = "Package: hello\nVersion: 2.10-2\nMaintainer: Santiago Vila <sanvila@debian.org>\nFilename: pool/main/h/hello/hello_2.10-2_amd64.deb\nSize: 56132\nDescription: example package based on GNU hello\n";
+        let root = temp_dir("failover");
+        let mut mirrors = Vec::new();
+        for (name, content) in [
+            ("broken", None),
+            ("outdated", Some("Package: outdated\n")),
+            ("good", Some(packages)),
+        ] {
+            let mirror = root.join(name);
+            match content {
+                Some(content) => {
+                    TestRepo::new()
+                        .listed("main/binary-amd64/Packages", packages.as_bytes())
+                        .file("main/binary-amd64/Packages", content.as_bytes())
+                        .build(&mirror);
+                }
+                None => fs::create_dir_all(&mirror).unwrap(),
+            }
+            mirrors.push(mirror.to_str().unwrap().to_string());
+        }
+
+        let list = root.join("mirrors.txt");
+        fs::write(&list, mirrors.join("\n")).unwrap();
+        let distro = Distro::repo(
+            &format!("mirror+file://{}", list.to_str().unwrap()),
+            "local",
+            Key::NoSignatureCheck,
+        );
+
+        // The broken mirror has no InRelease file.
+        let release = Release::from_distro(&distro).await.unwrap();
+        assert_eq!(
+            distro.served_by(&distro.in_release_url().unwrap()),
+            Some(mirrors[1].clone())
+        );
+
+        // The outdated mirror fails the hash verification.
+        let index = PackageIndex::new(&release, "main", &Architecture::Amd64)
+            .await
+            .unwrap();
+        assert!(index.get("hello", None).is_some());
+
+        let link = release
+            .get_package_index_link("main", &Architecture::Amd64)
+            .await
+            .unwrap();
+        assert_eq!(distro.served_by(&link.url), Some(mirrors[2].clone()));
+        assert_eq!(release.download_index(&link).await.unwrap(), packages);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn download_index_pdiff() {
+        use flate2::{write::GzEncoder, Compression};
+        use sha2::{Digest, Sha256};
+        use std::fs;
+        use std::io::Write;
+
+        let old = "Package: hello\nVersion: 1.0\n\nPackage: world\nVersion: 1.0\n";
+        let new = "Package: hello\nVersion: 2.0\n\nPackage: world\nVersion: 1.0\n";
+        let patch = "2c\nVersion: 2.0\n.\n";
+        let sha256 = |data: &[u8]| format!("{:x}", Sha256::digest(data));
+
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(patch.as_bytes()).unwrap();
+        let patch_gz = gz.finish().unwrap();
+
+        let pdiff_index = format!(
+            "SHA256-Current: {} {}\nSHA256-History:\n {} {} T-1\nSHA256-Patches:\n {} {} T-1\nSHA256-Download:\n {} {} T-1.gz\n",
+            sha256(new.as_bytes()),
+            new.len(),
+            sha256(old.as_bytes()),
+            old.len(),
+            sha256(patch.as_bytes()),
+            patch.len(),
+            sha256(&patch_gz),
+            patch_gz.len()
+        );
+
+        let root = temp_dir("pdiff");
+        let mut distro = TestRepo::new()
+            .listed("main/binary-amd64/Packages", new.as_bytes())
+            .index(
+                "main/binary-amd64/Packages.diff/Index",
+                pdiff_index.as_bytes(),
+            )
+            .file("main/binary-amd64/Packages.diff/T-1.gz", &patch_gz)
+            .build(&root);
+        let index_dir = root.join("dists/local/main/binary-amd64");
+
+        let cache = root.join("cache");
+        distro.set_cache(cache.to_str().unwrap());
+
+        let release = Release::from_distro(&distro).await.unwrap();
+        let link = release
+            .links
+            .values()
+            .find(|l| l.url.ends_with("/Packages"))
+            .unwrap()
+            .clone();
+
+        // The full index is downloaded if no cached index exists.
+        fs::write(index_dir.join("Packages"), new).unwrap();
+        assert_eq!(release.download_index(&link).await.unwrap(), new);
+        assert_eq!(
+            crate::cache::read_index(cache.to_str().unwrap(), &link.url).unwrap(),
+            new
+        );
+        fs::remove_file(index_dir.join("Packages")).unwrap();
+
+        // Cached old index is updated using the PDiff.
+        crate::cache::write_index(cache.to_str().unwrap(), &link.url, old);
+        assert_eq!(release.download_index(&link).await.unwrap(), new);
+        assert_eq!(
+            crate::cache::read_index(cache.to_str().unwrap(), &link.url).unwrap(),
+            new
+        );
+
+        // Up to date index doesn't need any download.
+        fs::remove_dir_all(index_dir.join("Packages.diff")).unwrap();
+        assert_eq!(release.download_index(&link).await.unwrap(), new);
+
+        // Unknown index version falls back to the full index.
+        crate::cache::write_index(cache.to_str().unwrap(), &link.url, "Package: other\n");
+        fs::write(index_dir.join("Packages"), new).unwrap();
+        assert_eq!(release.download_index(&link).await.unwrap(), new);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn package_index_compression_order() {
+        use crate::{Architecture, Compression};
+        use std::fs;
+        use std::io::Write;
+
+        let packages = b"Package: hello\n";
+
+        let mut bz2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bz2.write_all(packages).unwrap();
+        let bz2 = bz2.finish().unwrap();
+
+        let mut lz4 = lz4_flex::frame::FrameEncoder::new(Vec::new());
+        lz4.write_all(packages).unwrap();
+        let lz4 = lz4.finish().unwrap();
+
+        let root = temp_dir("compression");
+        let mut distro = TestRepo::new()
+            .index("main/binary-amd64/Packages.bz2", &bz2)
+            .index("main/binary-amd64/Packages.lz4", &lz4)
+            .build(&root);
+        let release = Release::from_distro(&distro).await.unwrap();
+        let link = release
+            .get_package_index_link("main", &Architecture::Amd64)
+            .await
+            .unwrap();
+        assert!(link.url.ends_with("Packages.bz2"));
+        assert_eq!(
+            release.download_index(&link).await.unwrap(),
+            "Package: hello\n"
+        );
+
+        distro.compressions = vec![Compression::Lz4, Compression::None];
+        let release = Release::from_distro(&distro).await.unwrap();
+        let link = release
+            .get_package_index_link("main", &Architecture::Amd64)
+            .await
+            .unwrap();
+        assert!(link.url.ends_with("Packages.lz4"));
+        assert_eq!(
+            release.download_index(&link).await.unwrap(),
+            "Package: hello\n"
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn package_index_probing() {
+        use crate::Architecture;
+        use std::fs;
+
+        // The listed Packages.xz doesn't exist on the mirror.
+        let root = temp_dir("probing");
+        let mut distro = TestRepo::new()
+            .listed("main/binary-amd64/Packages.xz", b"")
+            .index("main/binary-amd64/Packages.gz", b"")
+            .build(&root);
+        let release = Release::from_distro(&distro).await.unwrap();
+        let link = release
+            .get_package_index_link("main", &Architecture::Amd64)
+            .await
+            .unwrap();
+        assert!(link.url.ends_with("Packages.xz"));
+
+        distro.probe_indices = true;
+        let release = Release::from_distro(&distro).await.unwrap();
+        let link = release
+            .get_package_index_link("main", &Architecture::Amd64)
+            .await
+            .unwrap();
+        assert!(link.url.ends_with("Packages.gz"));
+
+        // Not listed indices are never used.
+        assert!(release
+            .get_package_index_link("main", &Architecture::Arm64)
+            .await
+            .is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_package_indices() {
+        use crate::test_util::MemoryTransport;
+        use crate::Architecture;
+        use sha2::{Digest, Sha256};
+        use std::sync::Arc;
+
+        let transport = MemoryTransport::new().with_delay(Duration::from_millis(20));
+        let mut in_release = "Suite: memory\nSHA256:\n".to_string();
+        for component in ["main", "contrib", "non-free"] {
+            for architecture in ["amd64", "arm64"] {
+                let path = format!("{component}/binary-{architecture}/Packages");
+                let packages = format!(
+                    "Package: {component}-{architecture}\nVersion: 1.0\nMaintainer: Test <test@example.com>\nFilename: pool/test_1.0_{architecture}.deb\nSize: 100\nDescription: test\n"
+                );
+                in_release += &format!(
+                    " {:x} {} {path}\n",
+                    Sha256::digest(packages.as_bytes()),
+                    packages.len()
+                );
+                transport.set(
+                    &format!("memory://repo/dists/memory/{path}"),
+                    packages.as_bytes(),
+                );
+            }
+        }
+        transport.set(
+            "memory://repo/dists/memory/InRelease",
+            in_release.as_bytes(),
+        );
+        let transport = Arc::new(transport);
+
+        let mut distro = Distro::repo("memory://repo", "memory", Key::NoSignatureCheck);
+        distro.transport = transport.clone();
+        distro.parallel_downloads = 2;
+        let release = Release::from_distro(&distro).await.unwrap();
+
+        let components: Vec<String> = ["main", "contrib", "non-free", "missing"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        let indices = release
+            .get_package_indices(&components, &[Architecture::Amd64, Architecture::Arm64])
+            .await;
+
+        assert_eq!(indices.len(), 8);
+        assert_eq!(transport.max_active(), 2);
+
+        // The results are in the order of the request.
+        let (component, architecture, index) = &indices[1];
+        assert_eq!(component, "contrib");
+        assert_eq!(architecture, &Architecture::Amd64);
+        assert!(index.as_ref().unwrap().get("contrib-amd64", None).is_some());
+
+        let (component, _, index) = &indices[3];
+        assert_eq!(component, "missing");
+        assert!(index.is_err());
+
+        assert_eq!(indices.iter().filter(|(_, _, i)| i.is_ok()).count(), 6);
+    }
+
+    #[test]
+    fn check_freshness() {
+        let distro = Distro::repo("/srv/mirror", "stable", Key::NoSignatureCheck);
+        let mut release = Release::new(&distro);
+        release.date =
+            Some(DateTime::parse_from_rfc2822("Mon, 01 Jan 2024 00:00:00 +0000").unwrap());
+        release.valid_until =
+            Some(DateTime::parse_from_rfc2822("Mon, 08 Jan 2024 00:00:00 +0000").unwrap());
+
+        let at = |date: &str| {
+            let mut freshness = Freshness::default();
+            freshness.now = Some(DateTime::parse_from_rfc2822(date).unwrap().to_utc());
+            freshness
+        };
+
+        // Valid.
+        release
+            .check_freshness(&at("Wed, 03 Jan 2024 00:00:00 +0000"))
+            .unwrap();
+
+        // Expired.
+        let error = release
+            .check_freshness(&at("Tue, 09 Jan 2024 00:00:00 +0000"))
+            .unwrap_err();
+        assert_eq!(error.error_type(), &ErrorType::Freshness);
+
+        // Expired, but within clock skew tolerance.
+        release
+            .check_freshness(&at("Mon, 08 Jan 2024 00:05:00 +0000"))
+            .unwrap();
+
+        // Not yet valid.
+        let error = release
+            .check_freshness(&at("Sun, 31 Dec 2023 00:00:00 +0000"))
+            .unwrap_err();
+        assert_eq!(error.error_type(), &ErrorType::Freshness);
+
+        // Valid-Until check disabled.
+        let mut freshness = at("Tue, 09 Jan 2024 00:00:00 +0000");
+        freshness.check_valid_until = false;
+        release.check_freshness(&freshness).unwrap();
+
+        // Maximum age is earlier than Valid-Until.
+        let mut freshness = at("Fri, 05 Jan 2024 00:00:00 +0000");
+        freshness.max_age = Some(Duration::from_secs(2 * 24 * 60 * 60));
+        assert!(release.check_freshness(&freshness).is_err());
+
+        // Maximum age without Valid-Until.
+        release.valid_until = None;
+        assert!(release.check_freshness(&freshness).is_err());
+        freshness.max_age = Some(Duration::from_secs(7 * 24 * 60 * 60));
+        release.check_freshness(&freshness).unwrap();
+
+        // Maximum age without date.
+        release.date = None;
+        assert!(release.check_freshness(&freshness).is_err());
+
+        release.check_freshness(&Freshness::disabled()).unwrap();
+    }
+
+    #[test]
+    fn check_freshness_overflow() {
+        let distro = Distro::repo("/srv/mirror", "stable", Key::NoSignatureCheck);
+        let mut release = Release::new(&distro);
+        release.date =
+            Some(DateTime::parse_from_rfc2822("Mon, 01 Jan 2024 00:00:00 +0000").unwrap());
+
+        let mut freshness = Freshness::default();
+        freshness.now = Some(
+            DateTime::parse_from_rfc2822("Tue, 09 Jan 2024 00:00:00 +0000")
+                .unwrap()
+                .to_utc(),
+        );
+
+        // Huge maximum age, the Release never expires.
+        freshness.max_age = Some(Duration::MAX);
+        release.check_freshness(&freshness).unwrap();
+
+        // Huge clock skew in addition.
+        freshness.clock_skew = Duration::MAX;
+        release.check_freshness(&freshness).unwrap();
+
+        // Huge clock skew with a date in the future.
+        freshness.max_age = None;
+        release.date = Some(DateTime::parse_from_rfc2822("01 Jan 2524 00:00:00 +0000").unwrap());
+        release.check_freshness(&freshness).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reject_expired_release() {
+        use std::fs;
+
+        let root = temp_dir("expired");
+        let mut distro = TestRepo::new()
+            .field("Date: Mon, 01 Jan 2024 00:00:00 UTC")
+            .field("Valid-Until: Mon, 08 Jan 2024 00:00:00 UTC")
+            .build(&root);
+        let error = Release::from_distro(&distro).await.unwrap_err();
+        assert_eq!(error.error_type(), &ErrorType::Freshness);
+
+        distro.freshness.check_valid_until = false;
+        Release::from_distro(&distro).await.unwrap();
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn detached_release_wrong_key() {
+        let key = Key::armored_key(&test_data("keys/other.asc"));
+        let distro = Distro::repo(&test_data("repo"), "legacy", key);
+
+        assert!(Release::from_distro(&distro).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_wrong_key() {
         // Ubuntu Jammy signing key.
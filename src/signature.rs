@@ -1,27 +1,52 @@
 //! GPG signature verification.
 
 #[cfg(not(test))]
-use log::{error, info};
+use log::{error, info, warn};
 
 #[cfg(test)]
-use std::{println as error, println as info};
+use std::{println as error, println as info, println as warn};
 
+use chrono::{DateTime, Duration, Utc};
 use pgp::cleartext::CleartextSignedMessage;
-use pgp::{Deserializable, SignedPublicKey};
-use std::fs::{self, File};
-use std::io::BufReader;
+use pgp::crypto::hash::HashAlgorithm;
+use pgp::packet::SignatureType;
+use pgp::types::{Fingerprint, PublicKeyTrait, PublicParams};
+use pgp::StandaloneSignature;
+use pgp::{Deserializable, SignedPublicKey, SignedPublicSubKey};
+use serde::{Deserialize, Serialize};
+use std::fs;
 
-use crate::util::download;
-use crate::{Distro, Error, Key, Result};
+use crate::util::{download_file, is_local, local_path, on_mirrors};
+use crate::{Distro, Error, Key, LinkHash, Result};
 
-/// Get the content of an armored key as string.
+/// The SignatureInfo describes the signature of a verified Release file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SignatureInfo {
+    /// Fingerprint of the key which made the signature.
+    pub fingerprint: String,
+    /// Fingerprint of the primary key, differs from _fingerprint_ if a subkey was used.
+    pub primary_fingerprint: String,
+    /// Long key ID of the key which made the signature.
+    pub key_id: String,
+    /// Creation time of the signature.
+    pub created: Option<DateTime<Utc>>,
+    /// Hash algorithm of the signature, e.g. _SHA256_.
+    pub hash_algorithm: String,
+    /// True if the signature was made using a subkey.
+    pub subkey: bool,
+}
+
+/// Armor header of a public key block.
+pub(crate) const PUBLIC_KEY_BLOCK: &str = "-----BEGIN PGP PUBLIC KEY BLOCK-----";
+
+/// Get the content of a key file.
 ///
-/// If the given url starts with http a download is tried,
+/// If the given url is not local a download is tried,
 /// else the url is interpreted as local file path.
-async fn _get_key_content(url: &str) -> Result<String> {
-    if url.starts_with("http") {
+async fn _get_key_content(url: &str, distro: &Distro) -> Result<Vec<u8>> {
+    if !is_local(url) {
         info!("Download key from URL {url}.");
-        match download(&url).await {
+        match download_file(distro, url).await {
             Ok(content) => Ok(content),
             Err(e) => {
                 let message = format!("Download of key {url} failed! {e}");
@@ -31,7 +56,7 @@ async fn _get_key_content(url: &str) -> Result<String> {
         }
     } else {
         info!("Download key from file {url}.");
-        match fs::read_to_string(url) {
+        match fs::read(local_path(url)) {
             Ok(content) => Ok(content),
             Err(e) => {
                 let message = format!("Reading key {url} failed! {e}");
@@ -42,81 +67,776 @@ async fn _get_key_content(url: &str) -> Result<String> {
     }
 }
 
-/// Get the signing key for the given Distro.
-async fn _get_key(distro: &Distro) -> Result<Option<SignedPublicKey>> {
-    let key = match &distro.key {
+/// Parse all keys of an armored key file.
+///
+/// The file may contain several armored key blocks.
+fn _parse_armored_keys(content: &str, url: &str) -> Result<Vec<SignedPublicKey>> {
+    let mut keys = Vec::new();
+
+    for block in content.split(PUBLIC_KEY_BLOCK).skip(1) {
+        let block = format!("{PUBLIC_KEY_BLOCK}{block}");
+        let (parsed, _headers) = SignedPublicKey::from_string_many(&block).map_err(|e| {
+            Error::new(
+                &format!("Loading key {url} failed! {e}"),
+                crate::ErrorType::Verification,
+            )
+        })?;
+
+        for key in parsed {
+            keys.push(key.map_err(|e| {
+                Error::new(
+                    &format!("Loading key {url} failed! {e}"),
+                    crate::ErrorType::Verification,
+                )
+            })?);
+        }
+    }
+
+    if keys.is_empty() {
+        return Err(Error::new(
+            &format!("No armored key found in {url}!"),
+            crate::ErrorType::Verification,
+        ));
+    }
+
+    Ok(keys)
+}
+
+/// Parse all keys of a non-armored key file.
+fn _parse_keys(content: &[u8], url: &str) -> Result<Vec<SignedPublicKey>> {
+    let keys: std::result::Result<Vec<SignedPublicKey>, _> =
+        SignedPublicKey::from_bytes_many(content).collect();
+    let keys = keys.map_err(|e| {
+        Error::new(
+            &format!("Loading key {url} failed! {e}"),
+            crate::ErrorType::Verification,
+        )
+    })?;
+
+    if keys.is_empty() {
+        return Err(Error::new(
+            &format!("No key found in {url}!"),
+            crate::ErrorType::Verification,
+        ));
+    }
+
+    Ok(keys)
+}
+
+/// Get the locations of the keys in a key directory.
+///
+/// Like apt, files with the extension _.gpg_ are used as non-armored keys,
+/// and files with the extension _.asc_ are used as armored keys.
+/// All other files are ignored.
+fn _get_key_dir(path: &str) -> Result<Vec<Key>> {
+    let entries = fs::read_dir(local_path(path)).map_err(|e| {
+        Error::new(
+            &format!("Reading key directory {path} failed! {e}"),
+            crate::ErrorType::Verification,
+        )
+    })?;
+
+    let mut files: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| path.to_str().map(|p| p.to_string()))
+        .collect();
+    files.sort();
+
+    let keys = files
+        .iter()
+        .filter_map(|file| {
+            if file.ends_with(".gpg") {
+                Some(Key::key(file))
+            } else if file.ends_with(".asc") {
+                Some(Key::armored_key(file))
+            } else {
+                info!("Ignoring {file} in key directory {path}.");
+                None
+            }
+        })
+        .collect();
+
+    Ok(keys)
+}
+
+/// Load the keys of a key file, or of an inline armored key.
+///
+/// Returns no keys for other Key variants.
+async fn _load_key_file(key: &Key, distro: &Distro) -> Result<Vec<SignedPublicKey>> {
+    match key {
+        Key::ArmoredKey(url) if url.trim_start().starts_with(PUBLIC_KEY_BLOCK) => {
+            info!("Use inline armored key for {:?}.", &distro.name);
+            _parse_armored_keys(url, "inline key")
+        }
         Key::ArmoredKey(url) => {
             info!("Get armored key for {:?} from {url}.", &distro.name);
-            let content = _get_key_content(&url).await?;
-            let (public_key, _headers_public) =
-                SignedPublicKey::from_string(&content).map_err(|e| {
-                    Error::new(
-                        &format!("Loading key {url} failed! {e}"),
-                        crate::ErrorType::Verification,
-                    )
-                })?;
-            public_key
+            let content = _get_key_content(url, distro).await?;
+            let content = String::from_utf8_lossy(&content);
+            _parse_armored_keys(&content, url)
         }
         Key::Key(url) => {
             info!("Get de-armored key for {:?} from {url}.", &distro.name);
-            let file = File::open(url).map_err(|e| {
-                Error::new(
-                    &format!("Loading key failed! {e}"),
-                    crate::ErrorType::Verification,
-                )
-            })?;
+            let content = _get_key_content(url, distro).await?;
+            _parse_keys(&content, url)
+        }
+        _ => Ok(Vec::new()),
+    }
+}
 
-            SignedPublicKey::from_bytes(BufReader::new(file)).map_err(|e| {
-                info!("Signature verification skipped for {:?}.", &distro.name);
-                Error::new(
-                    &format!("Loading key failed! {e}"),
-                    crate::ErrorType::Verification,
-                )
-            })?
+/// Get all signing keys for the given Distro.
+///
+/// Like apt, unreadable or malformed files of a key directory are skipped.
+///
+/// Returns None if no signature check is wanted.
+async fn _get_keys(distro: &Distro) -> Result<Option<Vec<SignedPublicKey>>> {
+    if distro.key == Key::NoSignatureCheck {
+        info!("No key for distro {:?}.", &distro.name);
+        return Ok(None);
+    }
+
+    let mut pending = vec![distro.key.clone()];
+    let mut keys = Vec::new();
+
+    while let Some(key) = pending.pop() {
+        match key {
+            Key::KeyDir(path) => {
+                info!("Get keys for {:?} from directory {path}.", &distro.name);
+                for file in _get_key_dir(&path)? {
+                    match _load_key_file(&file, distro).await {
+                        Ok(mut file_keys) => keys.append(&mut file_keys),
+                        Err(e) => warn!("Skipping key {file:?} of directory {path}. {e}"),
+                    }
+                }
+            }
+            Key::Keyring(mut ring) => {
+                ring.reverse();
+                pending.append(&mut ring);
+            }
+            Key::NoSignatureCheck => {}
+            key => keys.append(&mut _load_key_file(&key, distro).await?),
         }
-        Key::NoSignatureCheck => {
-            info!("No key for distro {:?}.", &distro.name);
-            return Ok(None);
+    }
+
+    let keys: Vec<SignedPublicKey> = keys
+        .into_iter()
+        .filter(|key| match key.verify() {
+            Ok(_) => {
+                info!(
+                    "Public key {} for distro {:?} is OK!",
+                    fingerprint_hex(&key.fingerprint()),
+                    &distro.name
+                );
+                true
+            }
+            Err(e) => {
+                warn!(
+                    "Public key {} for distro {:?} is NOT OK! {e}",
+                    fingerprint_hex(&key.fingerprint()),
+                    &distro.name
+                );
+                false
+            }
+        })
+        .collect();
+
+    if keys.is_empty() {
+        let message = format!("No valid public key for distro {:?}!", &distro.name);
+        error!("{}", &message);
+        return Err(Error::new(&message, crate::ErrorType::Verification));
+    }
+
+    Ok(Some(keys))
+}
+
+/// Format a fingerprint as upper case hex string.
+pub fn fingerprint_hex(fingerprint: &Fingerprint) -> String {
+    fingerprint
+        .as_bytes()
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect()
+}
+
+/// Check if the signer is allowed by the Signed-By fingerprints.
+///
+/// Like apt, a fingerprint of a primary key also allows its subkeys,
+/// and a fingerprint with the suffix "!" only allows exactly this key.
+/// If no fingerprints are given, all keys are allowed.
+fn _is_allowed_signer(signed_by: &[String], primary: &str, signer: &str) -> bool {
+    if signed_by.is_empty() {
+        return true;
+    }
+
+    signed_by.iter().any(|fingerprint| {
+        let fingerprint: String = fingerprint
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_uppercase();
+
+        match fingerprint.strip_suffix('!') {
+            Some(exact) => exact == signer,
+            None => fingerprint == signer || fingerprint == primary,
         }
+    })
+}
+
+/// Get the strength of a signature hash algorithm.
+///
+/// Returns None for unknown algorithms.
+fn _hash_strength(hash: HashAlgorithm) -> Option<LinkHash> {
+    match hash {
+        HashAlgorithm::MD5 => Some(LinkHash::Md5),
+        HashAlgorithm::SHA1 | HashAlgorithm::RIPEMD160 => Some(LinkHash::Sha1),
+        HashAlgorithm::SHA2_224
+        | HashAlgorithm::SHA2_256
+        | HashAlgorithm::SHA2_384
+        | HashAlgorithm::SHA3_256 => Some(LinkHash::Sha256),
+        HashAlgorithm::SHA2_512 | HashAlgorithm::SHA3_512 => Some(LinkHash::Sha512),
+        _ => None,
+    }
+}
+
+/// Get the size in bits of a RSA, DSA or Elgamal key.
+///
+/// Returns None for other key types.
+fn _key_bits(key: &impl PublicKeyTrait) -> Option<usize> {
+    let mpi = match key.public_params() {
+        PublicParams::RSA { n, .. } => n,
+        PublicParams::DSA { p, .. } => p,
+        PublicParams::Elgamal { p, .. } => p,
+        _ => return None,
     };
 
-    match key.verify() {
-        Ok(_) => info!("Public key for distro {:?} is OK!", &distro.name),
-        Err(e) => {
-            let message = format!("Public key for distro {:?} is NOT OK! {e}", &distro.name);
-            error!("{}", &message);
-            return Err(Error::new(&message, crate::ErrorType::Verification));
-        }
+    let bytes = mpi.as_bytes();
+    match bytes.first() {
+        Some(first) => Some(bytes.len() * 8 - first.leading_zeros() as usize),
+        None => Some(0),
+    }
+}
+
+/// Get the expiration time of a key.
+///
+/// The _expiration_ is the key expiration time of the self-signature,
+/// relative to the creation of the key. A duration of zero means no expiration.
+fn _expires_at(created: &DateTime<Utc>, expiration: Option<Duration>) -> Option<DateTime<Utc>> {
+    match expiration {
+        Some(expiration) if !expiration.is_zero() => Some(*created + expiration),
+        _ => None,
+    }
+}
+
+/// Check if a subkey may be used for signing.
+///
+/// Like gpg, the subkey must not be revoked,
+/// and the newest binding signature must allow signing.
+///
+/// Returns the reason if the subkey is rejected.
+fn _check_subkey(subkey: &SignedPublicSubKey) -> std::result::Result<(), String> {
+    let signer = fingerprint_hex(&subkey.fingerprint());
+
+    if subkey
+        .signatures
+        .iter()
+        .any(|s| s.typ() == SignatureType::SubkeyRevocation)
+    {
+        return Err(format!("Subkey {signer} is revoked."));
+    }
+
+    let binding = subkey
+        .signatures
+        .iter()
+        .filter(|s| s.typ() == SignatureType::SubkeyBinding)
+        .max_by_key(|s| s.created());
+    match binding {
+        Some(binding) if binding.key_flags().sign() => Ok(()),
+        _ => Err(format!("Subkey {signer} is not a signing key.")),
+    }
+}
+
+/// Check a valid signature against the verification policy of the Distro.
+///
+/// Returns the reason if the signature is rejected.
+fn _check_policy(
+    signature: &StandaloneSignature,
+    key: &SignedPublicKey,
+    subkey: Option<&SignedPublicSubKey>,
+    distro: &Distro,
+) -> std::result::Result<(), String> {
+    let policy = &distro.policy;
+
+    let hash = signature.signature.hash_alg();
+    match _hash_strength(hash) {
+        Some(strength) if strength >= policy.min_signature_hash => {}
+        _ => return Err(format!("Signature hash {hash} is too weak.")),
+    }
+
+    let (signer, bits) = match subkey {
+        Some(subkey) => (subkey.fingerprint(), _key_bits(subkey)),
+        None => (key.fingerprint(), _key_bits(key)),
     };
+    let signer = fingerprint_hex(&signer);
+
+    if let Some(bits) = bits {
+        if bits < policy.min_key_bits {
+            return Err(format!("Key {signer} has only {bits} bits."));
+        }
+    }
+
+    if !policy.allow_expired_keys {
+        let now = distro.freshness.now.unwrap_or_else(Utc::now);
+
+        let mut expires = vec![_expires_at(
+            key.primary_key.created_at(),
+            key.details.key_expiration_time(),
+        )];
+        if let Some(subkey) = subkey {
+            let expiration = subkey
+                .signatures
+                .iter()
+                .filter_map(|s| s.key_expiration_time())
+                .max()
+                .cloned();
+            expires.push(_expires_at(subkey.key.created_at(), expiration));
+        }
+
+        if let Some(expired) = expires.into_iter().flatten().find(|e| *e < now) {
+            return Err(format!("Key {signer} expired at {expired}."));
+        }
+    }
 
-    Ok(Some(key))
+    Ok(())
+}
+
+/// Find the key, or subkey, which produced a valid signature of the content.
+///
+/// Only signatures of usable subkeys which comply with the verification policy
+/// of the Distro are used.
+/// The reasons of valid, but rejected, signatures are added to _rejected_.
+fn _find_signer<'a>(
+    signatures: &'a [StandaloneSignature],
+    content: &[u8],
+    keys: &'a [SignedPublicKey],
+    distro: &Distro,
+    rejected: &mut Vec<String>,
+) -> Option<(
+    &'a StandaloneSignature,
+    &'a SignedPublicKey,
+    Option<&'a SignedPublicSubKey>,
+)> {
+    let signed_by = &distro.signed_by;
+
+    for signature in signatures {
+        for key in keys {
+            let primary = fingerprint_hex(&key.fingerprint());
+
+            let mut candidates = vec![None];
+            candidates.extend(key.public_subkeys.iter().map(Some));
+
+            for subkey in candidates {
+                let signer = match subkey {
+                    Some(subkey) => fingerprint_hex(&subkey.fingerprint()),
+                    None => primary.clone(),
+                };
+
+                if !_is_allowed_signer(signed_by, &primary, &signer) {
+                    continue;
+                }
+
+                let verified = match subkey {
+                    Some(subkey) => signature.verify(subkey, content),
+                    None => signature.verify(key, content),
+                };
+                if verified.is_err() {
+                    continue;
+                }
+
+                let checked = match subkey {
+                    Some(subkey) => _check_subkey(subkey),
+                    None => Ok(()),
+                };
+                match checked.and_then(|()| _check_policy(signature, key, subkey, distro)) {
+                    Ok(()) => return Some((signature, key, subkey)),
+                    Err(reason) => {
+                        warn!("Signature of {signer} rejected! {reason}");
+                        rejected.push(reason);
+                    }
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Verify the signatures using the keys of the Distro.
+///
+/// The signature is valid if any allowed key or subkey produced a valid signature.
+fn _verify(
+    signatures: &[StandaloneSignature],
+    content: &[u8],
+    keys: &[SignedPublicKey],
+    distro: &Distro,
+    file: &str,
+) -> Result<SignatureInfo> {
+    let mut rejected = Vec::new();
+
+    match _find_signer(signatures, content, keys, distro, &mut rejected) {
+        Some((signature, key, subkey)) => {
+            let (fingerprint, key_id) = match subkey {
+                Some(subkey) => (subkey.fingerprint(), subkey.key_id()),
+                None => (key.fingerprint(), key.key_id()),
+            };
+            let info = SignatureInfo {
+                fingerprint: fingerprint_hex(&fingerprint),
+                primary_fingerprint: fingerprint_hex(&key.fingerprint()),
+                key_id: format!("{:X}", key_id),
+                created: signature.signature.created().cloned(),
+                hash_algorithm: signature.signature.hash_alg().to_string(),
+                subkey: subkey.is_some(),
+            };
+            info!(
+                "{file} signature for distro {:?} is OK! Signed by {}.",
+                &distro.name, &info.fingerprint
+            );
+            Ok(info)
+        }
+        None => {
+            let mut message = format!(
+                "{file} signature for distro {:?} is NOT OK! No valid signature of an allowed key found.",
+                &distro.name
+            );
+            for reason in rejected {
+                message += &format!(" {reason}");
+            }
+            error!("{}", &message);
+            Err(Error::new(&message, crate::ErrorType::Verification))
+        }
+    }
 }
 
 /// Verify the signature of the InRelease file.
 ///
 /// The full content of the inline signed file is given as content.
 /// The given Distro is used to specify the signing key.
-pub async fn verify_in_release(content: String, distro: &Distro) -> Result<String> {
+///
+/// Returns the signed text, and the signature info if the signature was checked.
+pub async fn verify_in_release(
+    content: String,
+    distro: &Distro,
+) -> Result<(String, Option<SignatureInfo>)> {
     info!("Verifying signature of distro {:?}.", &distro.name);
 
-    let key = match _get_key(distro).await? {
-        Some(key) => key,
-        None => return Ok(content),
+    let keys = match _get_keys(distro).await? {
+        Some(keys) => keys,
+        None => return Ok((content, None)),
     };
 
-    let (inrelease, _headers_msg) = CleartextSignedMessage::from_string(&content).unwrap();
+    let (inrelease, _headers_msg) = CleartextSignedMessage::from_string(&content).map_err(|e| {
+        Error::new(
+            &format!("Parsing InRelease of distro {:?} failed! {e}", &distro.name),
+            crate::ErrorType::Verification,
+        )
+    })?;
 
-    match inrelease.verify(&key) {
-        Ok(_) => info!("InRelease signature for distro {:?} is OK!", &distro.name),
-        Err(e) => {
-            let message = format!(
-                "InRelease signature for distro {:?} is NOT OK! {e}",
-                &distro.name
-            );
-            error!("{}", &message);
-            return Err(Error::new(&message, crate::ErrorType::Verification));
+    let info = _verify(
+        inrelease.signatures(),
+        inrelease.signed_text().as_bytes(),
+        &keys,
+        distro,
+        "InRelease",
+    )?;
+
+    Ok((inrelease.text().to_string(), Some(info)))
+}
+
+/// Verify the detached signature of the Release file.
+///
+/// The content of the Release file is given as content.
+/// The detached signature is downloaded from the _Release.gpg_ URL of the Distro,
+/// using the mirror which served the Release file if it is known.
+/// The given Distro is used to specify the signing key.
+///
+/// Returns the content, and the signature info if the signature was checked.
+pub async fn verify_release(
+    content: String,
+    distro: &Distro,
+) -> Result<(String, Option<SignatureInfo>)> {
+    info!("Verifying detached signature of distro {:?}.", &distro.name);
+
+    let keys = match _get_keys(distro).await? {
+        Some(keys) => keys,
+        None => return Ok((content, None)),
+    };
+
+    // The signature must come from the mirror which served the Release file,
+    // since mirrors may be out of sync.
+    let url = distro.release_gpg_url()?;
+    let data = match distro.served_by(&distro.release_url()?) {
+        Some(mirror) => {
+            let mirror_url = distro.mirror_url(&url, &mirror)?;
+            let data = download_file(distro, &mirror_url).await?;
+            distro.record_mirror(&url, &mirror);
+            data
         }
+        None => {
+            on_mirrors(distro, &url, |url| async move {
+                download_file(distro, &url).await
+            })
+            .await?
+        }
+    };
+
+    let signatures: std::result::Result<Vec<StandaloneSignature>, _> =
+        if data.starts_with(b"-----BEGIN") {
+            let data = String::from_utf8_lossy(&data);
+            StandaloneSignature::from_string_many(&data)
+                .and_then(|(signatures, _headers)| signatures.collect())
+        } else {
+            StandaloneSignature::from_bytes_many(&data[..]).collect()
+        };
+    let signatures = signatures.map_err(|e| {
+        Error::new(
+            &format!("Parsing signature {url} failed! {e}"),
+            crate::ErrorType::Verification,
+        )
+    })?;
+
+    let info = _verify(&signatures, content.as_bytes(), &keys, distro, "Release")?;
+
+    Ok((content, Some(info)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{temp_dir, test_data, test_fingerprint};
+    use crate::VerificationPolicy;
+
+    /// Verify the InRelease of the given test distribution.
+    async fn verify(
+        dist: &str,
+        key: Key,
+        signed_by: Vec<String>,
+    ) -> Result<(String, Option<SignatureInfo>)> {
+        let mut distro = Distro::repo(&test_data("repo"), dist, key);
+        distro.signed_by = signed_by;
+        verify_distro(&distro).await
     }
 
-    Ok(inrelease.text().to_string())
+    /// Verify the InRelease of the given test Distro.
+    async fn verify_distro(distro: &Distro) -> Result<(String, Option<SignatureInfo>)> {
+        let dist = distro.name.as_ref().unwrap();
+        let content =
+            fs::read_to_string(test_data(&format!("repo/dists/{dist}/InRelease"))).unwrap();
+        verify_in_release(content, distro).await
+    }
+
+    /// Assert that the verification fails with a message containing _reason_.
+    fn assert_rejected(result: Result<(String, Option<SignatureInfo>)>, reason: &str) {
+        let message = result.unwrap_err().message().unwrap().to_string();
+        assert!(message.contains(reason), "{message}");
+    }
+
+    #[tokio::test]
+    async fn reject_weak_signature_hash() {
+        let key = Key::armored_key(&test_data("keys/test.asc"));
+        let mut distro = Distro::repo(&test_data("repo"), "weak", key);
+
+        assert_rejected(verify_distro(&distro).await, "SHA1 is too weak");
+
+        distro.policy.min_signature_hash = LinkHash::Sha1;
+        let (_, info) = verify_distro(&distro).await.unwrap();
+        assert_eq!(info.unwrap().hash_algorithm, "SHA1");
+    }
+
+    #[tokio::test]
+    async fn reject_small_key() {
+        let key = Key::armored_key(&test_data("keys/small.asc"));
+        let mut distro = Distro::repo(&test_data("repo"), "small", key);
+
+        assert_rejected(verify_distro(&distro).await, "has only 1024 bits");
+
+        distro.policy.min_key_bits = 1024;
+        verify_distro(&distro).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reject_expired_key() {
+        let key = Key::armored_key(&test_data("keys/expired.asc"));
+        let mut distro = Distro::repo(&test_data("repo"), "expired", key);
+
+        assert_rejected(verify_distro(&distro).await, "expired at 2020-12-31");
+
+        distro.policy.allow_expired_keys = true;
+        verify_distro(&distro).await.unwrap();
+
+        distro.policy.allow_expired_keys = false;
+        distro.freshness.now = Some("2020-06-02T00:00:00Z".parse().unwrap());
+        verify_distro(&distro).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn permissive_policy() {
+        for (dist, key) in [
+            ("weak", "keys/test.asc"),
+            ("small", "keys/small.asc"),
+            ("expired", "keys/expired.asc"),
+        ] {
+            let mut distro =
+                Distro::repo(&test_data("repo"), dist, Key::armored_key(&test_data(key)));
+            distro.policy = VerificationPolicy::permissive();
+            verify_distro(&distro).await.unwrap();
+        }
+    }
+
+    #[test]
+    fn key_bits() {
+        let content = fs::read_to_string(test_data("keys/test.asc")).unwrap();
+        let keys = _parse_armored_keys(&content, "test.asc").unwrap();
+        assert_eq!(_key_bits(&keys[0]), Some(3072));
+    }
+
+    #[test]
+    fn allowed_signer() {
+        assert!(_is_allowed_signer(&[], "AAAA", "BBBB"));
+
+        let signed_by = vec!["aa aa".to_string()];
+        assert!(_is_allowed_signer(&signed_by, "AAAA", "AAAA"));
+        assert!(_is_allowed_signer(&signed_by, "AAAA", "BBBB"));
+        assert!(!_is_allowed_signer(&signed_by, "CCCC", "CCCC"));
+
+        let signed_by = vec!["AAAA!".to_string()];
+        assert!(_is_allowed_signer(&signed_by, "AAAA", "AAAA"));
+        assert!(!_is_allowed_signer(&signed_by, "AAAA", "BBBB"));
+    }
+
+    #[tokio::test]
+    async fn multi_key_files() {
+        for key in [
+            Key::key(&test_data("keys/keyring.gpg")),
+            Key::armored_key(&test_data("keys/keyring.asc")),
+        ] {
+            verify("stable", key, Vec::new()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn inline_armored_key() {
+        let content = fs::read_to_string(test_data("keys/test.asc")).unwrap();
+        verify("stable", Key::armored_key(&content), Vec::new())
+            .await
+            .unwrap();
+
+        let content = fs::read_to_string(test_data("keys/other.asc")).unwrap();
+        assert!(verify("stable", Key::armored_key(&content), Vec::new())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn key_directory() {
+        let key = Key::key_dir(&test_data("keys/trusted.gpg.d"));
+        verify("stable", key.clone(), Vec::new()).await.unwrap();
+        verify("subkey", key, Vec::new()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn key_directory_with_broken_files() {
+        let dir = temp_dir("broken-key-dir");
+        for entry in fs::read_dir(test_data("keys/trusted.gpg.d")).unwrap() {
+            let path = entry.unwrap().path();
+            fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+        }
+        fs::write(dir.join("broken.gpg"), b"Not a key.").unwrap();
+        fs::write(
+            dir.join("broken.asc"),
+            format!("{PUBLIC_KEY_BLOCK}\nbroken\n"),
+        )
+        .unwrap();
+
+        let key = Key::key_dir(dir.to_str().unwrap());
+        verify("stable", key.clone(), Vec::new()).await.unwrap();
+        verify("subkey", key, Vec::new()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reject_unusable_subkeys() {
+        let key = Key::armored_key(&test_data("keys/revoked.asc"));
+        assert_rejected(verify("revoked", key, Vec::new()).await, "is revoked");
+
+        let key = Key::armored_key(&test_data("keys/unflagged.asc"));
+        assert_rejected(
+            verify("unflagged", key, Vec::new()).await,
+            "is not a signing key",
+        );
+    }
+
+    #[tokio::test]
+    async fn keyring_of_keys() {
+        let key = Key::keyring(vec![
+            Key::armored_key(&test_data("keys/other.asc")),
+            Key::key(&test_data("keys/test.gpg")),
+        ]);
+        verify("stable", key, Vec::new()).await.unwrap();
+
+        let key = Key::keyring(vec![Key::armored_key(&test_data("keys/other.asc"))]);
+        assert!(verify("stable", key, Vec::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn key_rotation() {
+        // Signed by the old and the new key, any of the keys is accepted.
+        for key in ["keys/test.asc", "keys/other.asc"] {
+            verify("rotation", Key::armored_key(&test_data(key)), Vec::new())
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn subkey_signature() {
+        let key = Key::armored_key(&test_data("keys/subkey.asc"));
+        verify("subkey", key, Vec::new()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn signed_by_fingerprints() {
+        let keyring = Key::key(&test_data("keys/keyring.gpg"));
+
+        verify("stable", keyring.clone(), vec![test_fingerprint("test")])
+            .await
+            .unwrap();
+        assert!(
+            verify("stable", keyring.clone(), vec![test_fingerprint("other")])
+                .await
+                .is_err()
+        );
+        verify("rotation", keyring, vec![test_fingerprint("other")])
+            .await
+            .unwrap();
+
+        // Primary key fingerprint allows subkeys.
+        let key = Key::armored_key(&test_data("keys/subkey.asc"));
+        verify("subkey", key.clone(), vec![test_fingerprint("subkey")])
+            .await
+            .unwrap();
+        // Exact primary key doesn't allow subkeys.
+        assert!(verify(
+            "subkey",
+            key.clone(),
+            vec![format!("{}!", test_fingerprint("subkey"))]
+        )
+        .await
+        .is_err());
+        verify(
+            "subkey",
+            key,
+            vec![format!("{}!", test_fingerprint("subkey-sign"))],
+        )
+        .await
+        .unwrap();
+    }
 }
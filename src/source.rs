@@ -11,7 +11,7 @@ use std::{cmp::Ordering, collections::HashMap};
 
 use crate::util::{parse_package_relation, parse_stanza};
 use crate::{
-    Architecture, Distro, Error, ErrorType, Link, LinkHash, PackageVersion, Priority, Result,
+    Architecture, DependencyGroup, Distro, Error, ErrorType, Link, LinkHash, Priority, Result,
     Version,
 };
 
@@ -56,12 +56,12 @@ pub struct Source {
     pub testsuite: Vec<String>,
     pub dgit: Option<String>,
     pub standards_version: Option<String>,
-    pub build_depends: Vec<PackageVersion>,
-    pub build_depends_indep: Vec<PackageVersion>,
-    pub build_depends_arch: Vec<PackageVersion>,
-    pub build_conflicts: Vec<PackageVersion>,
-    pub build_conflicts_indep: Vec<PackageVersion>,
-    pub build_conflicts_arch: Vec<PackageVersion>,
+    pub build_depends: DependencyGroup,
+    pub build_depends_indep: DependencyGroup,
+    pub build_depends_arch: DependencyGroup,
+    pub build_conflicts: DependencyGroup,
+    pub build_conflicts_indep: DependencyGroup,
+    pub build_conflicts_arch: DependencyGroup,
     pub package_list: Vec<PackageReference>,
     // The links group the checksums with the size and the hash,
     // for all checksums and files.
@@ -104,12 +104,12 @@ impl Source {
             testsuite: Vec::new(),
             dgit: None,
             standards_version: None,
-            build_depends: Vec::new(),
-            build_depends_indep: Vec::new(),
-            build_depends_arch: Vec::new(),
-            build_conflicts: Vec::new(),
-            build_conflicts_indep: Vec::new(),
-            build_conflicts_arch: Vec::new(),
+            build_depends: DependencyGroup::default(),
+            build_depends_indep: DependencyGroup::default(),
+            build_depends_arch: DependencyGroup::default(),
+            build_conflicts: DependencyGroup::default(),
+            build_conflicts_indep: DependencyGroup::default(),
+            build_conflicts_arch: DependencyGroup::default(),
             package_list: Vec::new(),
             // The links group the checksums with the size and the hash,
             // for all checksums and files.
@@ -508,6 +508,26 @@ impl Source {
         Ok(source)
     }
 
+    /// Get the build dependencies which apply to a build for the host architecture
+    /// with the given active build profiles, e.g. _nocheck_.
+    ///
+    /// Use [DependencyGroup::reduce] for the other relation fields,
+    /// e.g. _build_depends_arch_.
+    pub fn build_depends_for(&self, host: &Architecture, profiles: &[&str]) -> DependencyGroup {
+        self.build_depends.reduce(host, profiles)
+    }
+
+    /// Check if the source package builds on the given architecture,
+    /// i.e. one of the architecture entries is the architecture or a matching
+    /// wildcard like _linux-any_ or _any-amd64_.
+    ///
+    /// Sources with architecture _all_ build on each architecture.
+    pub fn builds_on(&self, architecture: &Architecture) -> bool {
+        self.architecture
+            .iter()
+            .any(|entry| entry == &Architecture::All || architecture.matches(&entry.name()))
+    }
+
     fn parse_files(
         &mut self,
         files: Option<&String>,
@@ -630,32 +650,37 @@ Checksums-Sha512:
         assert_eq!(source.standards_version, Some("3.9.8".to_string()));
 
         assert_eq!(source.build_depends.len(), 4);
+        assert_eq!(
+            source.build_depends_for(&Architecture::Riscv64, &["nocheck"]),
+            source.build_depends
+        );
+        let build_depends = source.build_depends.packages();
 
-        assert_eq!(source.build_depends[0].name, "debhelper-compat");
+        assert_eq!(build_depends[0].name, "debhelper-compat");
         assert_eq!(
-            source.build_depends[0].version,
+            build_depends[0].version,
             Some(Version::from_str("9").unwrap())
         );
         assert_eq!(
-            source.build_depends[0].relation,
+            build_depends[0].relation,
             Some(VersionRelation::from_str("=").unwrap())
         );
 
-        assert_eq!(source.build_depends[1].name, "dh-python");
-        assert_eq!(source.build_depends[1].version, None);
-        assert_eq!(source.build_depends[1].relation, None);
+        assert_eq!(build_depends[1].name, "dh-python");
+        assert_eq!(build_depends[1].version, None);
+        assert_eq!(build_depends[1].relation, None);
 
-        assert_eq!(source.build_depends[2].name, "python3-all");
-        assert_eq!(source.build_depends[2].version, None);
-        assert_eq!(source.build_depends[2].relation, None);
+        assert_eq!(build_depends[2].name, "python3-all");
+        assert_eq!(build_depends[2].version, None);
+        assert_eq!(build_depends[2].relation, None);
 
-        assert_eq!(source.build_depends[3].name, "python3-setuptools");
+        assert_eq!(build_depends[3].name, "python3-setuptools");
         assert_eq!(
-            source.build_depends[3].version,
+            build_depends[3].version,
             Some(Version::from_str("0.6b3").unwrap())
         );
         assert_eq!(
-            source.build_depends[3].relation,
+            build_depends[3].relation,
             Some(VersionRelation::from_str(">=").unwrap())
         );
 
@@ -740,4 +765,31 @@ Checksums-Sha512:
         );
         assert_eq!(link.hashes.get(&LinkHash::Sha512).unwrap(), "4795112fc25d74214a89df6ecdb935fd107f3b8cce79c49cd0c1b57354f914e10b90857eec3c78dd10c8234ff69d4825c8ab7c06cf317a6d11a8f40a98e62aeb");
     }
+
+    #[test]
+    fn builds_on() {
+        let mut source = Source::new(
+            "3.0 (quilt)",
+            "test",
+            Version::from_str("1.0-1").unwrap(),
+            "Test <test@example.com>",
+            "pool/main/t/test",
+        );
+
+        source.architecture = vec![
+            Architecture::from_str("linux-any").unwrap(),
+            Architecture::from_str("any-arm64").unwrap(),
+        ];
+        assert!(source.builds_on(&Architecture::Riscv64));
+        assert!(source.builds_on(&Architecture::from_str("musl-linux-amd64").unwrap()));
+        assert!(source.builds_on(&Architecture::from_str("freebsd-arm64").unwrap()));
+        assert!(!source.builds_on(&Architecture::from_str("kfreebsd-amd64").unwrap()));
+
+        source.architecture = vec![Architecture::Amd64, Architecture::I386];
+        assert!(source.builds_on(&Architecture::Amd64));
+        assert!(!source.builds_on(&Architecture::Riscv64));
+
+        source.architecture = vec![Architecture::All];
+        assert!(source.builds_on(&Architecture::Riscv64));
+    }
 }
@@ -3,7 +3,6 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-use crate::util::download_compressed;
 pub use crate::Result;
 use crate::{Architecture, Error, Link, PackageVersion, Release, Source};
 
@@ -35,16 +34,12 @@ impl SourceIndex {
 
     /// Download the source package index, verify the hash, and parse the content.
     async fn parse_index(&mut self, link: &Link, release: &Release) -> Result<()> {
-        let content = download_compressed(&link).await?;
-
-        for stanza in content.split("\n\n") {
-            let stanza = stanza.trim();
-
-            if stanza.is_empty() {
-                continue;
-            }
+        let results = release
+            .parse_index(link, |stanza| Source::from_stanza(stanza, &release.distro))
+            .await?;
 
-            match Source::from_stanza(stanza, &release.distro) {
+        for result in results {
+            match result {
                 Ok(source) => self.add(source),
                 Err(e) => self.issues.push(e),
             }
@@ -0,0 +1,1010 @@
+//! Parsing and writing of apt sources.list entries,
+//! in the one-line format and the deb822 format of _.sources_ files.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::signature::PUBLIC_KEY_BLOCK;
+use crate::stream::Stanzas;
+use crate::util::parse_stanza;
+use crate::{Architecture, Distro, Error, ErrorType, Key, Result};
+
+/// Default key location of apt, used if an entry has no _signed-by_ key file.
+pub const DEFAULT_KEY_DIR: &str = "/etc/apt/trusted.gpg.d";
+
+/// One-line option names which differ from the deb822 field names.
+const RENAMED_OPTIONS: [(&str, &str); 3] = [
+    ("arch", "architectures"),
+    ("lang", "languages"),
+    ("target", "targets"),
+];
+
+/// deb822 fields which are provided as fields of the [SourceEntry].
+const ENTRY_FIELDS: [&str; 8] = [
+    "types",
+    "uris",
+    "suites",
+    "components",
+    "architectures",
+    "signed-by",
+    "trusted",
+    "enabled",
+];
+
+/// Type of a sources.list entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SourceType {
+    /// Binary packages, i.e. _deb_.
+    Deb,
+    /// Source packages, i.e. _deb-src_.
+    DebSrc,
+}
+
+impl FromStr for SourceType {
+    type Err = Error;
+
+    fn from_str(source_type: &str) -> Result<SourceType> {
+        match source_type {
+            "deb" => Ok(SourceType::Deb),
+            "deb-src" => Ok(SourceType::DebSrc),
+            _ => Err(Error::new(
+                &format!("Unknown source type {source_type}!"),
+                ErrorType::SourcesList,
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SourceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SourceType::Deb => "deb",
+            SourceType::DebSrc => "deb-src",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// A SourceEntry is an apt repository definition, i.e. a line of a sources.list file.
+///
+/// The one-line format is _type [options] uri suite [component...]_,
+/// e.g. `deb [arch=amd64 signed-by=/usr/share/keyrings/x.gpg] http://archive.ubuntu.com/ubuntu jammy main`.
+/// If the _suite_ ends with a _/_, e.g. _./_, the entry is a flat repository without components.
+///
+/// The options _arch_, _signed-by_ and _trusted_ are provided as fields,
+/// all other options are kept as name and value in _options_.
+/// The name includes the modifier of _+=_ and _-=_ options, e.g. _arch+_.
+///
+/// A deb822 stanza, see [parse_sources], provides one entry for each combination
+/// of its _Types_, _URIs_ and _Suites_. The fields and options use the one-line names,
+/// e.g. the field _Languages_ is the option _lang_ and _Architectures-Add_ is _arch+_.
+/// The _signed_by_ value can also be an inline armored key block of a _Signed-By_ field.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SourceEntry {
+    pub source_type: SourceType,
+    pub uri: String,
+    pub suite: String,
+    pub components: Vec<String>,
+    /// Requested architectures, empty for all architectures of the repository.
+    pub architectures: Vec<Architecture>,
+    /// Key files and full fingerprints of the _signed-by_ option.
+    pub signed_by: Vec<String>,
+    /// Value of the _trusted_ option, if given.
+    pub trusted: Option<bool>,
+    /// False for entries disabled using _Enabled: no_.
+    pub enabled: bool,
+    pub options: Vec<(String, String)>,
+}
+
+impl SourceEntry {
+    /// Create a new entry without options.
+    pub fn new(
+        source_type: SourceType,
+        uri: &str,
+        suite: &str,
+        components: &[&str],
+    ) -> SourceEntry {
+        SourceEntry {
+            source_type,
+            uri: uri.to_string(),
+            suite: suite.to_string(),
+            components: components.iter().map(|c| c.to_string()).collect(),
+            architectures: Vec::new(),
+            signed_by: Vec::new(),
+            trusted: None,
+            enabled: true,
+            options: Vec::new(),
+        }
+    }
+
+    /// Parse a line of the one-line sources.list format.
+    ///
+    /// Comments, i.e. everything after a _#_, are ignored.
+    /// Returns None if the line is empty or a comment.
+    pub fn from_line(line: &str) -> Result<Option<SourceEntry>> {
+        let line = match line.find('#') {
+            Some(pos) => &line[..pos],
+            None => line,
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        let (source_type, rest) = match line.split_once(char::is_whitespace) {
+            Some((source_type, rest)) => (source_type, rest.trim_start()),
+            None => (line, ""),
+        };
+        let source_type = SourceType::from_str(source_type)?;
+
+        let (options, rest) = match rest.strip_prefix('[') {
+            Some(rest) => match rest.split_once(']') {
+                Some((options, rest)) => (Some(options), rest),
+                None => {
+                    return Err(Error::new(
+                        &format!("Options of line {line} are not closed!"),
+                        ErrorType::SourcesList,
+                    ))
+                }
+            },
+            None => (None, rest),
+        };
+
+        let mut fields = rest.split_whitespace();
+        let (uri, suite) = match (fields.next(), fields.next()) {
+            (Some(uri), Some(suite)) => (uri, suite),
+            _ => {
+                return Err(Error::new(
+                    &format!("URI or suite of line {line} missing!"),
+                    ErrorType::SourcesList,
+                ))
+            }
+        };
+
+        let mut entry = SourceEntry::new(source_type, uri, suite, &fields.collect::<Vec<_>>());
+
+        if let Some(options) = options {
+            for option in options.split_whitespace() {
+                entry.add_option(option)?;
+            }
+        }
+
+        entry.check()?;
+
+        Ok(Some(entry))
+    }
+
+    /// Add an option given as _name=value_, _name+=value_ or _name-=value_.
+    fn add_option(&mut self, option: &str) -> Result<()> {
+        let (name, value) = match option.split_once('=') {
+            Some((name, value)) if !name.is_empty() => (name, value),
+            _ => {
+                return Err(Error::new(
+                    &format!("Invalid option {option}!"),
+                    ErrorType::SourcesList,
+                ))
+            }
+        };
+
+        match name {
+            "arch" => {
+                for arch in value.split(',').filter(|a| !a.is_empty()) {
+                    self.architectures.push(Architecture::from_str(arch)?);
+                }
+            }
+            "signed-by" => {
+                self.signed_by.extend(
+                    value
+                        .split(',')
+                        .filter(|v| !v.is_empty())
+                        .map(|v| v.to_string()),
+                );
+            }
+            "trusted" => self.trusted = Some(parse_yes_no(name, value)?),
+            _ => self.options.push((name.to_string(), value.to_string())),
+        }
+
+        Ok(())
+    }
+
+    /// Parse a stanza of a deb822 sources file.
+    ///
+    /// Returns one entry for each combination of _Types_, _URIs_ and _Suites_.
+    pub fn from_stanza(stanza: &str) -> Result<Vec<SourceEntry>> {
+        let kv = parse_stanza(stanza);
+
+        let list = |field: &str| -> Vec<&str> {
+            match kv.get(field) {
+                Some(value) => value.split_whitespace().collect(),
+                None => Vec::new(),
+            }
+        };
+
+        let types = list("types");
+        let uris = list("uris");
+        let suites = list("suites");
+        for (field, values) in [("Types", &types), ("URIs", &uris), ("Suites", &suites)] {
+            if values.is_empty() {
+                return Err(Error::new(
+                    &format!("Field {field} missing!"),
+                    ErrorType::SourcesList,
+                ));
+            }
+        }
+
+        let mut template = SourceEntry::new(SourceType::Deb, "", "", &list("components"));
+        for arch in list("architectures") {
+            template.architectures.push(Architecture::from_str(arch)?);
+        }
+        if let Some(signed_by) = kv.get("signed-by") {
+            template.signed_by = parse_signed_by(signed_by);
+        }
+        if let Some(trusted) = kv.get("trusted") {
+            template.trusted = Some(parse_yes_no("trusted", trusted)?);
+        }
+        if let Some(enabled) = kv.get("enabled") {
+            template.enabled = parse_yes_no("enabled", enabled)?;
+        }
+
+        let mut fields: Vec<&String> = kv
+            .keys()
+            .filter(|field| !ENTRY_FIELDS.contains(&field.as_str()))
+            .collect();
+        fields.sort();
+        for field in fields {
+            let value: Vec<&str> = kv[field].split_whitespace().collect();
+            template.options.push((option_name(field), value.join(",")));
+        }
+
+        let mut entries = Vec::new();
+        for source_type in &types {
+            let source_type = SourceType::from_str(source_type)?;
+            for uri in &uris {
+                for suite in &suites {
+                    let mut entry = template.clone();
+                    entry.source_type = source_type;
+                    entry.uri = uri.to_string();
+                    entry.suite = suite.to_string();
+                    entry.check()?;
+                    entries.push(entry);
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Check that the entry is complete, i.e. a flat repository has no components,
+    /// and a default repository has at least one component.
+    fn check(&self) -> Result<()> {
+        if let Some(key_id) = self.signed_by.iter().find(|value| is_key_id(value)) {
+            Err(Error::new(
+                &format!(
+                    "Signed-by of {} {} must use full fingerprints, not the key ID {key_id}!",
+                    self.uri, self.suite
+                ),
+                ErrorType::SourcesList,
+            ))
+        } else if self.is_flat() && !self.components.is_empty() {
+            Err(Error::new(
+                &format!(
+                    "Flat repository {} {} must not have components!",
+                    self.uri, self.suite
+                ),
+                ErrorType::SourcesList,
+            ))
+        } else if !self.is_flat() && self.components.is_empty() {
+            Err(Error::new(
+                &format!("Components of {} {} missing!", self.uri, self.suite),
+                ErrorType::SourcesList,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check if the entry is a flat repository, i.e. the suite is a path ending with _/_.
+    pub fn is_flat(&self) -> bool {
+        self.suite.ends_with('/')
+    }
+
+    /// Get the options in the one-line format, without brackets.
+    fn option_list(&self) -> Vec<String> {
+        let mut options = Vec::new();
+
+        if !self.architectures.is_empty() {
+            let architectures: Vec<String> = self.architectures.iter().map(|a| a.name()).collect();
+            options.push(format!("arch={}", architectures.join(",")));
+        }
+        if !self.signed_by.is_empty() {
+            options.push(format!("signed-by={}", self.signed_by.join(",")));
+        }
+        if let Some(trusted) = self.trusted {
+            options.push(format!("trusted={}", if trusted { "yes" } else { "no" }));
+        }
+        for (name, value) in &self.options {
+            options.push(format!("{name}={value}"));
+        }
+
+        options
+    }
+
+    /// Write the entry in the one-line sources.list format.
+    ///
+    /// Disabled entries are written as comment.
+    /// Inline keys are not supported by the one-line format,
+    /// use [SourceEntry::to_stanza] for entries with inline keys.
+    pub fn to_line(&self) -> String {
+        let mut fields = vec![self.source_type.to_string()];
+        if !self.enabled {
+            fields.insert(0, "#".to_string());
+        }
+
+        let options = self.option_list();
+        if !options.is_empty() {
+            fields.push(format!("[{}]", options.join(" ")));
+        }
+
+        fields.push(self.uri.clone());
+        fields.push(self.suite.clone());
+        fields.extend(self.components.iter().cloned());
+
+        fields.join(" ")
+    }
+
+    /// Write the entry as stanza of a deb822 sources file.
+    pub fn to_stanza(&self) -> String {
+        format_stanza(
+            &[self.source_type],
+            &[self.uri.as_str()],
+            &[self.suite.as_str()],
+            self,
+        )
+    }
+
+    /// Check if both entries only differ in the type, the URI and the suite.
+    fn same_options(&self, other: &SourceEntry) -> bool {
+        self.components == other.components
+            && self.architectures == other.architectures
+            && self.signed_by == other.signed_by
+            && self.trusted == other.trusted
+            && self.enabled == other.enabled
+            && self.options == other.options
+    }
+
+    /// Get the key of the entry.
+    ///
+    /// An entry with _trusted=yes_ is not verified, i.e. _Key::NoSignatureCheck_ is used.
+    /// The key files of _signed-by_, i.e. all values which are not fingerprints,
+    /// are used as keys. Files with the extension _.asc_ and inline keys are armored keys.
+    /// If no key file is given, the apt key directory [DEFAULT_KEY_DIR] is used.
+    pub fn key(&self) -> Key {
+        if self.trusted == Some(true) {
+            return Key::NoSignatureCheck;
+        }
+
+        let mut keys: Vec<Key> = self
+            .signed_by
+            .iter()
+            .filter(|value| !is_fingerprint(value))
+            .map(|file| {
+                if file.ends_with(".asc") || is_inline_key(file) {
+                    Key::armored_key(file)
+                } else {
+                    Key::key(file)
+                }
+            })
+            .collect();
+
+        match keys.len() {
+            0 => Key::key_dir(DEFAULT_KEY_DIR),
+            1 => keys.remove(0),
+            _ => Key::keyring(keys),
+        }
+    }
+
+    /// Create the [Distro] of the entry.
+    ///
+    /// The fingerprints of _signed-by_ are used as _Distro::signed_by_,
+    /// see [SourceEntry::key] for the used key.
+    /// The components and architectures are not part of the Distro,
+    /// use the fields of the entry to select the indices.
+    pub fn distro(&self) -> Result<Distro> {
+        self.check()?;
+
+        let mut distro = if self.is_flat() {
+            Distro::flat_repo(&self.uri, &self.suite, self.key())
+        } else {
+            Distro::repo(&self.uri, &self.suite, self.key())
+        };
+
+        distro.signed_by = self
+            .signed_by
+            .iter()
+            .filter(|value| is_fingerprint(value))
+            .cloned()
+            .collect();
+
+        Ok(distro)
+    }
+}
+
+impl fmt::Display for SourceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_line())
+    }
+}
+
+/// Parse the content of a sources.list file in the one-line format.
+///
+/// Empty lines and comments are skipped.
+pub fn parse_sources_list(content: &str) -> Result<Vec<SourceEntry>> {
+    let mut entries = Vec::new();
+
+    for (number, line) in content.lines().enumerate() {
+        let entry = SourceEntry::from_line(line).map_err(|e| {
+            Error::new(
+                &format!("Line {}: {}", number + 1, e.message().unwrap_or_default()),
+                ErrorType::SourcesList,
+            )
+        })?;
+
+        if let Some(entry) = entry {
+            entries.push(entry);
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Write the entries in the one-line sources.list format, one entry per line.
+pub fn format_sources_list(entries: &[SourceEntry]) -> String {
+    entries
+        .iter()
+        .map(|entry| format!("{}\n", entry.to_line()))
+        .collect()
+}
+
+/// Parse the content of a deb822 sources file, e.g. _/etc/apt/sources.list.d/ubuntu.sources_.
+///
+/// Comment lines are skipped. Each stanza provides one [SourceEntry]
+/// for each combination of its _Types_, _URIs_ and _Suites_, in this order.
+/// Disabled stanzas, i.e. _Enabled: no_, are included with _enabled_ false.
+pub fn parse_sources(content: &str) -> Result<Vec<SourceEntry>> {
+    let content: String = content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| format!("{line}\n"))
+        .collect();
+
+    let mut entries = Vec::new();
+
+    for (number, stanza) in Stanzas::new(content.as_bytes()).enumerate() {
+        let stanza = stanza.map_err(|e| Error::from_io_error(e, "sources"))?;
+        let mut stanza_entries = SourceEntry::from_stanza(&stanza).map_err(|e| {
+            Error::new(
+                &format!("Stanza {}: {}", number + 1, e.message().unwrap_or_default()),
+                ErrorType::SourcesList,
+            )
+        })?;
+        entries.append(&mut stanza_entries);
+    }
+
+    Ok(entries)
+}
+
+/// Write the entries in the deb822 sources format.
+///
+/// Consecutive entries which only differ in the type, URI and suite
+/// and form all combinations of them, e.g. the entries of a parsed stanza,
+/// are written as one stanza.
+pub fn format_sources(entries: &[SourceEntry]) -> String {
+    let mut stanzas = Vec::new();
+    let mut start = 0;
+
+    while start < entries.len() {
+        let mut end = start + 1;
+        while end < entries.len() && entries[end].same_options(&entries[start]) {
+            end += 1;
+        }
+        let group = &entries[start..end];
+
+        let mut types = Vec::new();
+        let mut uris = Vec::new();
+        let mut suites = Vec::new();
+        for entry in group {
+            if !types.contains(&entry.source_type) {
+                types.push(entry.source_type);
+            }
+            if !uris.contains(&entry.uri.as_str()) {
+                uris.push(entry.uri.as_str());
+            }
+            if !suites.contains(&entry.suite.as_str()) {
+                suites.push(entry.suite.as_str());
+            }
+        }
+
+        let mut combinations = Vec::new();
+        for source_type in &types {
+            for uri in &uris {
+                for suite in &suites {
+                    combinations.push((*source_type, *uri, *suite));
+                }
+            }
+        }
+        let complete = combinations.len() == group.len()
+            && combinations
+                .iter()
+                .zip(group)
+                .all(|((source_type, uri, suite), entry)| {
+                    entry.source_type == *source_type && entry.uri == *uri && entry.suite == *suite
+                });
+
+        if complete {
+            stanzas.push(format_stanza(&types, &uris, &suites, &group[0]));
+        } else {
+            stanzas.extend(group.iter().map(|entry| entry.to_stanza()));
+        }
+
+        start = end;
+    }
+
+    stanzas.join("\n")
+}
+
+/// Write a deb822 stanza for the given types, URIs and suites,
+/// using the other fields and options of the entry.
+fn format_stanza(
+    types: &[SourceType],
+    uris: &[&str],
+    suites: &[&str],
+    entry: &SourceEntry,
+) -> String {
+    let mut fields = Vec::new();
+
+    if !entry.enabled {
+        fields.push("Enabled: no".to_string());
+    }
+
+    let types: Vec<String> = types.iter().map(|t| t.to_string()).collect();
+    fields.push(format!("Types: {}", types.join(" ")));
+    fields.push(format!("URIs: {}", uris.join(" ")));
+    fields.push(format!("Suites: {}", suites.join(" ")));
+    if !entry.components.is_empty() {
+        fields.push(format!("Components: {}", entry.components.join(" ")));
+    }
+    if !entry.architectures.is_empty() {
+        let architectures: Vec<String> = entry.architectures.iter().map(|a| a.name()).collect();
+        fields.push(format!("Architectures: {}", architectures.join(" ")));
+    }
+    if !entry.signed_by.is_empty() {
+        let (inline, other): (Vec<&String>, Vec<&String>) = entry
+            .signed_by
+            .iter()
+            .partition(|value| is_inline_key(value));
+        let other: Vec<&str> = other.iter().map(|value| value.as_str()).collect();
+        let mut field = format!("Signed-By: {}", other.join(" "))
+            .trim_end()
+            .to_string();
+        for key in inline {
+            for line in key.trim().lines() {
+                match line.trim() {
+                    "" => field += "\n .",
+                    line => field += &format!("\n {line}"),
+                }
+            }
+        }
+        fields.push(field);
+    }
+    if let Some(trusted) = entry.trusted {
+        fields.push(format!("Trusted: {}", if trusted { "yes" } else { "no" }));
+    }
+    for (name, value) in &entry.options {
+        let value: Vec<&str> = value.split(',').collect();
+        fields.push(format!("{}: {}", field_name(name), value.join(" ")));
+    }
+
+    fields.iter().map(|field| format!("{field}\n")).collect()
+}
+
+/// Parse the value of a _Signed-By_ field.
+///
+/// The value is either an inline armored key block, where empty lines are written as _._,
+/// or a list of key files and fingerprints.
+fn parse_signed_by(value: &str) -> Vec<String> {
+    let value = value.trim();
+
+    if is_inline_key(value) {
+        let key: String = value
+            .lines()
+            .map(|line| match line.trim() {
+                "." => "\n".to_string(),
+                line => format!("{line}\n"),
+            })
+            .collect();
+        vec![key]
+    } else {
+        value
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string())
+            .collect()
+    }
+}
+
+/// Check if the _signed-by_ value is an inline armored key.
+fn is_inline_key(value: &str) -> bool {
+    value.trim_start().starts_with(PUBLIC_KEY_BLOCK)
+}
+
+/// Parse a _yes_ or _no_ value of the given option.
+fn parse_yes_no(name: &str, value: &str) -> Result<bool> {
+    match value {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err(Error::new(
+            &format!("Invalid value {value} of option {name}!"),
+            ErrorType::SourcesList,
+        )),
+    }
+}
+
+/// Get the one-line option name of a lowercase deb822 field name,
+/// e.g. _arch+_ for _architectures-add_.
+fn option_name(field: &str) -> String {
+    let (field, modifier) = if let Some(field) = field.strip_suffix("-add") {
+        (field, "+")
+    } else if let Some(field) = field.strip_suffix("-remove") {
+        (field, "-")
+    } else {
+        (field, "")
+    };
+
+    let name = RENAMED_OPTIONS
+        .iter()
+        .find(|(_, renamed)| *renamed == field)
+        .map(|(name, _)| *name)
+        .unwrap_or(field);
+
+    format!("{name}{modifier}")
+}
+
+/// Get the deb822 field name of a one-line option name,
+/// e.g. _Architectures-Add_ for _arch+_.
+fn field_name(option: &str) -> String {
+    let (option, suffix) = if let Some(option) = option.strip_suffix('+') {
+        (option, "-add")
+    } else if let Some(option) = option.strip_suffix('-') {
+        (option, "-remove")
+    } else {
+        (option, "")
+    };
+
+    let field = RENAMED_OPTIONS
+        .iter()
+        .find(|(name, _)| *name == option)
+        .map(|(_, field)| *field)
+        .unwrap_or(option);
+
+    format!("{field}{suffix}")
+        .split('-')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+/// Check if the _signed-by_ value is a key fingerprint, and not a key file.
+///
+/// Fingerprints are full fingerprints, i.e. hex strings of 40 digits, or 64 digits for v5 keys,
+/// optionally with the suffix _!_. Values containing a _/_ are always key files,
+/// also if the file name looks like hex, e.g. _./deadbeef_.
+fn is_fingerprint(value: &str) -> bool {
+    is_hex_id(value, &[40, 64])
+}
+
+/// Check if the _signed-by_ value is a short or long key ID.
+///
+/// Like apt, key IDs are not accepted, since they don't identify a key reliably.
+fn is_key_id(value: &str) -> bool {
+    is_hex_id(value, &[8, 16, 32])
+}
+
+/// Check if the value is a hex string of one of the given lengths,
+/// optionally with the suffix _!_.
+fn is_hex_id(value: &str, lengths: &[usize]) -> bool {
+    let value = value.strip_suffix('!').unwrap_or(value);
+    !value.contains('/')
+        && lengths.contains(&value.len())
+        && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_data;
+
+    #[test]
+    fn parse_lines() {
+        let content = r#"
+# Ubuntu Jammy
+deb [arch=amd64,arm64 signed-by=/usr/share/keyrings/ubuntu.gpg] http://archive.ubuntu.com/ubuntu jammy main universe
+deb-src http://archive.ubuntu.com/ubuntu jammy main # sources
+deb [trusted=yes lang=de] file:///srv/repo ./
+"#;
+        let entries = parse_sources_list(content).unwrap();
+        assert_eq!(entries.len(), 3);
+
+        let jammy = &entries[0];
+        assert_eq!(jammy.source_type, SourceType::Deb);
+        assert_eq!(jammy.uri, "http://archive.ubuntu.com/ubuntu");
+        assert_eq!(jammy.suite, "jammy");
+        assert_eq!(jammy.components, vec!["main", "universe"]);
+        assert_eq!(
+            jammy.architectures,
+            vec![Architecture::Amd64, Architecture::Arm64]
+        );
+        assert_eq!(jammy.signed_by, vec!["/usr/share/keyrings/ubuntu.gpg"]);
+
+        assert_eq!(entries[1].source_type, SourceType::DebSrc);
+        assert_eq!(entries[1].components, vec!["main"]);
+
+        let flat = &entries[2];
+        assert!(flat.is_flat());
+        assert_eq!(flat.trusted, Some(true));
+        assert_eq!(flat.options, vec![("lang".to_string(), "de".to_string())]);
+
+        assert_eq!(
+            format_sources_list(&entries),
+            "deb [arch=amd64,arm64 signed-by=/usr/share/keyrings/ubuntu.gpg] http://archive.ubuntu.com/ubuntu jammy main universe\n\
+             deb-src http://archive.ubuntu.com/ubuntu jammy main\n\
+             deb [trusted=yes lang=de] file:///srv/repo ./\n"
+        );
+        assert_eq!(
+            parse_sources_list(&format_sources_list(&entries)).unwrap(),
+            entries
+        );
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert!(SourceEntry::from_line("rpm http://example.com/repo stable main").is_err());
+        assert!(
+            SourceEntry::from_line("deb [arch=amd64 http://example.com/repo stable main").is_err()
+        );
+        assert!(SourceEntry::from_line("deb http://example.com/repo").is_err());
+        assert!(SourceEntry::from_line("deb http://example.com/repo stable").is_err());
+        assert!(SourceEntry::from_line("deb http://example.com/repo ./ main").is_err());
+        assert!(SourceEntry::from_line("deb [trusted=maybe] http://example.com/repo ./").is_err());
+
+        let err = parse_sources_list("deb http://example.com/repo stable main\ndeb\n").unwrap_err();
+        assert!(err.message().unwrap().starts_with("Line 2:"));
+    }
+
+    #[test]
+    fn parse_deb822() {
+        let content = r#"# Ubuntu sources
+Types: deb deb-src
+URIs: http://archive.ubuntu.com/ubuntu http://mirror.example.com/ubuntu
+Suites: noble noble-updates
+Components: main universe
+Architectures: amd64 i386
+Signed-By: /usr/share/keyrings/ubuntu-archive-keyring.gpg
+Languages: de en
+Architectures-Add: arm64
+
+## Disabled local repository
+Enabled: no
+Types: deb
+URIs: file:///srv/repo
+Suites: ./
+Trusted: yes
+"#;
+        let entries = parse_sources(content).unwrap();
+        assert_eq!(entries.len(), 9);
+
+        let first = &entries[0];
+        assert_eq!(first.source_type, SourceType::Deb);
+        assert_eq!(first.uri, "http://archive.ubuntu.com/ubuntu");
+        assert_eq!(first.suite, "noble");
+        assert_eq!(first.components, vec!["main", "universe"]);
+        assert_eq!(
+            first.architectures,
+            vec![Architecture::Amd64, Architecture::I386]
+        );
+        assert_eq!(
+            first.signed_by,
+            vec!["/usr/share/keyrings/ubuntu-archive-keyring.gpg"]
+        );
+        assert_eq!(
+            first.options,
+            vec![
+                ("arch+".to_string(), "arm64".to_string()),
+                ("lang".to_string(), "de,en".to_string()),
+            ]
+        );
+        assert!(first.enabled);
+
+        let last = &entries[7];
+        assert_eq!(last.source_type, SourceType::DebSrc);
+        assert_eq!(last.uri, "http://mirror.example.com/ubuntu");
+        assert_eq!(last.suite, "noble-updates");
+
+        let local = &entries[8];
+        assert!(!local.enabled);
+        assert!(local.is_flat());
+        assert_eq!(local.trusted, Some(true));
+        assert_eq!(local.to_line(), "# deb [trusted=yes] file:///srv/repo ./");
+
+        let formatted = format_sources(&entries);
+        assert_eq!(
+            formatted,
+            "Types: deb deb-src\n\
+             URIs: http://archive.ubuntu.com/ubuntu http://mirror.example.com/ubuntu\n\
+             Suites: noble noble-updates\n\
+             Components: main universe\n\
+             Architectures: amd64 i386\n\
+             Signed-By: /usr/share/keyrings/ubuntu-archive-keyring.gpg\n\
+             Architectures-Add: arm64\n\
+             Languages: de en\n\
+             \n\
+             Enabled: no\n\
+             Types: deb\n\
+             URIs: file:///srv/repo\n\
+             Suites: ./\n\
+             Trusted: yes\n"
+        );
+        assert_eq!(parse_sources(&formatted).unwrap(), entries);
+
+        // Entries which are not all combinations are written as separate stanzas.
+        assert_eq!(format_sources(&entries[..3]).matches("Types:").count(), 3);
+
+        assert!(parse_sources("Types: deb\nSuites: stable\nComponents: main\n").is_err());
+        assert!(parse_sources(
+            "Types: rpm\nURIs: http://example.com\nSuites: stable\nComponents: main\n"
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn inline_key() {
+        let key = std::fs::read_to_string(test_data("keys/test.asc")).unwrap();
+
+        let mut content = format!(
+            "Types: deb\nURIs: {}\nSuites: stable\nComponents: main\nSigned-By:\n",
+            test_data("repo")
+        );
+        for line in key.lines() {
+            match line.trim() {
+                "" => content += " .\n",
+                line => content += &format!(" {line}\n"),
+            }
+        }
+
+        let entries = parse_sources(&content).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(format_sources(&entries), content);
+
+        let distro = entries[0].distro().unwrap();
+        assert_eq!(distro.key, Key::armored_key(&entries[0].signed_by[0]));
+
+        let in_release = std::fs::read_to_string(test_data("repo/dists/stable/InRelease")).unwrap();
+        crate::signature::verify_in_release(in_release, &distro)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn fingerprints() {
+        assert!(is_fingerprint("0123456789ABCDEF0123456789ABCDEF01234567"));
+        assert!(is_fingerprint("0123456789abcdef0123456789abcdef01234567!"));
+        assert!(is_fingerprint(
+            "0123456789ABCDEF0123456789ABCDEF0123456789ABCDEF0123456789ABCDEF"
+        ));
+
+        assert!(!is_fingerprint("0123456789ABCDEF"));
+        assert!(!is_fingerprint("0123456789ABCDEF0123456789ABCDEF"));
+        assert!(!is_fingerprint("deadbeef"));
+        assert!(!is_fingerprint("cafe"));
+        assert!(!is_fingerprint("0123456789ABCDEF0"));
+        assert!(!is_fingerprint("./0123456789ABCDEF"));
+        assert!(!is_fingerprint("/usr/share/keyrings/ubuntu.gpg"));
+        assert!(!is_fingerprint(""));
+    }
+
+    #[test]
+    fn reject_key_ids() {
+        for key_id in [
+            "DEADBEEF",
+            "0123456789ABCDEF!",
+            "0123456789ABCDEF0123456789ABCDEF",
+        ] {
+            let line = format!("deb [signed-by={key_id}] http://example.com/debian bookworm main");
+            let error = parse_sources_list(&line).unwrap_err();
+            assert_eq!(error.error_type(), &ErrorType::SourcesList);
+
+            let content = format!(
+                "Types: deb\nURIs: http://example.com/debian\nSuites: bookworm\nComponents: main\nSigned-By: {key_id}\n"
+            );
+            let error = parse_sources(&content).unwrap_err();
+            assert_eq!(error.error_type(), &ErrorType::SourcesList);
+        }
+    }
+
+    #[tokio::test]
+    async fn signed_by_fingerprint() {
+        use crate::test_util::test_fingerprint;
+        use crate::Release;
+
+        for (name, allowed) in [("test", true), ("other", false)] {
+            let line = format!(
+                "deb [signed-by={},{}] file://{} stable main",
+                test_data("keys/keyring.gpg"),
+                test_fingerprint(name),
+                test_data("repo")
+            );
+            let entries = parse_sources_list(&line).unwrap();
+            let distro = entries[0].distro().unwrap();
+            assert_eq!(distro.signed_by, vec![test_fingerprint(name)]);
+
+            let release = Release::from_distro(&distro).await;
+            match allowed {
+                true => assert_eq!(
+                    release.unwrap().signature.unwrap().fingerprint,
+                    test_fingerprint("test")
+                ),
+                false => assert!(release.is_err()),
+            }
+        }
+    }
+
+    #[test]
+    fn entry_distro() {
+        let entry = SourceEntry::from_line(
+            "deb [signed-by=/usr/share/keyrings/a.gpg,/usr/share/keyrings/b.asc,0123456789ABCDEF0123456789ABCDEF01234567!] http://example.com/debian bookworm main",
+        )
+        .unwrap()
+        .unwrap();
+        let distro = entry.distro().unwrap();
+        assert_eq!(distro.url, "http://example.com/debian");
+        assert_eq!(distro.name, Some("bookworm".to_string()));
+        assert_eq!(
+            distro.key,
+            Key::keyring(vec![
+                Key::key("/usr/share/keyrings/a.gpg"),
+                Key::armored_key("/usr/share/keyrings/b.asc"),
+            ])
+        );
+        assert_eq!(
+            distro.signed_by,
+            vec!["0123456789ABCDEF0123456789ABCDEF01234567!"]
+        );
+        assert_eq!(
+            distro.in_release_url().unwrap(),
+            "http://example.com/debian/dists/bookworm/InRelease"
+        );
+
+        let entry = SourceEntry::from_line("deb [trusted=yes] file:///srv/repo ./")
+            .unwrap()
+            .unwrap();
+        let distro = entry.distro().unwrap();
+        assert_eq!(distro.name, None);
+        assert_eq!(distro.path, Some("./".to_string()));
+        assert_eq!(distro.key, Key::NoSignatureCheck);
+
+        let entry = SourceEntry::new(
+            SourceType::Deb,
+            "http://example.com/debian",
+            "stable",
+            &["main"],
+        );
+        assert_eq!(entry.distro().unwrap().key, Key::key_dir(DEFAULT_KEY_DIR));
+    }
+}
@@ -0,0 +1,478 @@
+//! Streaming download and parsing of large index files.
+
+#[cfg(not(test))]
+use log::info;
+
+#[cfg(test)]
+use std::println as info;
+
+use std::io::{BufRead, BufReader, Read};
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::event::{notify, Event, Observer};
+use crate::transport::{ChunkReader, Transport};
+use crate::util::{notify_verification, LinkHasher};
+use crate::{Compression, Distro, Error, ErrorType, Link, LinkHash, Result};
+
+/// Number of chunks and stanzas buffered between the pipeline stages.
+const BUFFER_SIZE: usize = 16;
+
+/// Iterator over the stanzas of an index file.
+///
+/// Stanzas are separated by empty lines, and returned without the separating lines.
+/// Invalid UTF-8 sequences are replaced.
+pub struct Stanzas<R: BufRead> {
+    reader: R,
+}
+
+impl<R: BufRead> Stanzas<R> {
+    /// Create an iterator over the stanzas of the reader.
+    pub fn new(reader: R) -> Stanzas<R> {
+        Stanzas { reader }
+    }
+}
+
+impl<R: BufRead> Iterator for Stanzas<R> {
+    type Item = std::io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut stanza = String::new();
+        let mut line = Vec::new();
+
+        loop {
+            line.clear();
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => {
+                    let text = String::from_utf8_lossy(&line);
+                    if !text.trim().is_empty() {
+                        stanza += text.trim_end();
+                        stanza.push('\n');
+                    } else if !stanza.is_empty() {
+                        break;
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        if stanza.is_empty() {
+            None
+        } else {
+            stanza.truncate(stanza.len() - 1);
+            Some(Ok(stanza))
+        }
+    }
+}
+
+/// Stream of the stanzas of a downloaded index file.
+///
+/// The index is downloaded, hashed, decompressed and split into stanzas
+/// while the stanzas are consumed, so the memory usage is bounded
+/// independent of the size of the index.
+///
+/// The hash of the index can only be verified after the last stanza was read.
+/// If the verification or the download fails, the stream ends with an error,
+/// and all stanzas received before must be discarded.
+/// The stream ends with None only if the complete index was verified.
+pub struct StanzaStream {
+    receiver: mpsc::Receiver<Result<String>>,
+    by_hash: bool,
+}
+
+impl StanzaStream {
+    /// Create a stream of the stanzas of the given, already verified, text.
+    pub fn from_text(text: &str) -> StanzaStream {
+        let stanzas: Vec<String> = Stanzas::new(text.as_bytes())
+            .map(|stanza| stanza.expect("Reading from memory never fails."))
+            .collect();
+
+        let (sender, receiver) = mpsc::channel(stanzas.len().max(1));
+        for stanza in stanzas {
+            let _ = sender.try_send(Ok(stanza));
+        }
+
+        StanzaStream {
+            receiver,
+            by_hash: false,
+        }
+    }
+
+    /// Check if the index is read using the Acquire-By-Hash URL.
+    ///
+    /// If such a stream ends with an error, the index should be read again
+    /// using the canonical URL, like [crate::util::download_compressed] does.
+    pub fn is_by_hash(&self) -> bool {
+        self.by_hash
+    }
+
+    /// Get the next stanza.
+    ///
+    /// None is returned at the end of the verified index.
+    pub async fn next(&mut self) -> Option<Result<String>> {
+        self.receiver.recv().await
+    }
+}
+
+/// Open the given Link, preferring the Acquire-By-Hash URL if _by_hash_ is true.
+///
+/// Returns the reader and the opened URL.
+async fn open(
+    transport: &dyn Transport,
+    link: &Link,
+    by_hash: bool,
+) -> Result<(Box<dyn ChunkReader>, String)> {
+    if by_hash {
+        if let Some(url) = link.by_hash_url() {
+            match transport.open(&url).await {
+                Ok(reader) => return Ok((reader, url)),
+                Err(e) => {
+                    info!("By-hash download {url} failed, using {}. {e}", &link.url);
+                }
+            }
+        }
+    }
+
+    Ok((transport.open(&link.url).await?, link.url.clone()))
+}
+
+/// Download, verify and decompress the given Link, and stream the stanzas.
+///
+/// The compression is detected in the same way as for [crate::util::download_compressed].
+/// If _by_hash_ is true, the file is fetched using the Acquire-By-Hash URL if possible.
+/// The canonical URL is only used if the Acquire-By-Hash URL can't be opened,
+/// since the stanzas are already yielded when the verification fails.
+/// Callers must discard the stanzas of a failed stream, and retry using
+/// the canonical URL if [StanzaStream::is_by_hash] is true.
+/// Only the given _hashes_ are accepted for verification.
+/// The file is fetched using the transport of the _distro_, without mirror failover,
+/// and the progress is reported to the observer of the _distro_.
+///
+/// Content failing the verification is evicted from the cache of the transport.
+///
+/// The download and hashing runs as tokio task, the decompression and
+/// stanza splitting runs as blocking task.
+pub async fn stream_compressed(
+    distro: &Distro,
+    link: &Link,
+    by_hash: bool,
+    hashes: &[LinkHash],
+) -> Result<StanzaStream> {
+    let mut hasher = LinkHasher::new(link, hashes)?;
+    let (mut reader, opened_url) = open(distro.transport.as_ref(), link, by_hash).await?;
+    let opened_by_hash = opened_url != link.url;
+    let transport = distro.transport.clone();
+    let observer = distro.observer.clone();
+
+    let (chunk_sender, chunk_receiver) = mpsc::channel::<Vec<u8>>(BUFFER_SIZE);
+    let (stanza_sender, stanza_receiver) = mpsc::channel(BUFFER_SIZE);
+    let (result_sender, result_receiver) = oneshot::channel();
+
+    let download_link = link.clone();
+    let download_observer = observer.clone();
+    tokio::spawn(async move {
+        let link = download_link;
+        let observer = download_observer;
+        let size = Some(link.size);
+        let mut received = 0;
+
+        notify(&observer, || Event::DownloadStarted {
+            url: link.url.clone(),
+            size,
+        });
+
+        let result = loop {
+            match reader.chunk().await {
+                Ok(Some(chunk)) => {
+                    received += chunk.len();
+                    notify(&observer, || Event::DownloadProgress {
+                        url: link.url.clone(),
+                        received,
+                        size,
+                    });
+
+                    hasher.update(&chunk);
+                    if chunk_sender.send(chunk).await.is_err() {
+                        // Decoding stopped.
+                        return;
+                    }
+                }
+                Ok(None) => {
+                    notify(&observer, || Event::DownloadFinished {
+                        url: link.url.clone(),
+                        received,
+                    });
+
+                    let hash = hasher.hash();
+                    let result = notify_verification(hasher.verify(), hash, &link, &observer);
+                    if result.is_err() {
+                        transport.evict(&opened_url);
+                    }
+                    break result;
+                }
+                Err(e) => break Err(e),
+            }
+        };
+
+        drop(chunk_sender);
+        let _ = result_sender.send(result);
+    });
+
+    let url = link.url.clone();
+    tokio::task::spawn_blocking(move || {
+        decode(
+            chunk_receiver,
+            result_receiver,
+            stanza_sender,
+            &url,
+            &observer,
+        );
+    });
+
+    Ok(StanzaStream {
+        receiver: stanza_receiver,
+        by_hash: opened_by_hash,
+    })
+}
+
+/// Decompress the received chunks and send the stanzas.
+///
+/// The verification result of the download is sent as last item, if it failed.
+fn decode(
+    chunks: mpsc::Receiver<Vec<u8>>,
+    verified: oneshot::Receiver<Result<()>>,
+    stanzas: mpsc::Sender<Result<String>>,
+    url: &str,
+    observer: &Option<Arc<dyn Observer>>,
+) {
+    let mut chunks = ChunkChannel {
+        receiver: chunks,
+        data: Vec::new(),
+        pos: 0,
+    };
+
+    let compression =
+        Compression::from_magic(chunks.peek(6)).unwrap_or_else(|| Compression::from_extension(url));
+    info!("Streaming {url} using compression {compression}.");
+
+    let mut consumed = true;
+    let mut size = 0;
+    let decoded = compression.reader(&mut chunks, url).and_then(|decoder| {
+        let decoder = CountingReader {
+            inner: decoder,
+            count: &mut size,
+        };
+        for stanza in Stanzas::new(BufReader::new(decoder)) {
+            let stanza = stanza.map_err(|e| Error::from_io_error(e, url))?;
+            if stanzas.blocking_send(Ok(stanza)).is_err() {
+                consumed = false;
+                break;
+            }
+        }
+        Ok(())
+    });
+
+    if !consumed {
+        // The stream was dropped.
+        return;
+    }
+
+    if decoded.is_ok() {
+        if compression != Compression::None {
+            notify(observer, || Event::Decompressed {
+                url: url.to_string(),
+                compression,
+                size,
+            });
+        }
+
+        // Data after the end of the compressed stream is hashed, but ignored.
+        while chunks.receiver.blocking_recv().is_some() {}
+    }
+    drop(chunks);
+
+    // A failed download or verification is the root cause of decoding errors.
+    let result = match (verified.blocking_recv(), decoded) {
+        (Ok(Err(e)), _) => Err(e),
+        (_, Err(e)) => Err(e),
+        (Ok(Ok(())), Ok(())) => Ok(()),
+        (Err(_), Ok(())) => Err(Error::new(
+            &format!("Download of {url} was aborted!"),
+            ErrorType::Download,
+        )),
+    };
+
+    if let Err(e) = result {
+        let _ = stanzas.blocking_send(Err(e));
+    }
+}
+
+/// Reader counting the bytes read from the inner reader.
+struct CountingReader<'a, R: Read> {
+    inner: R,
+    count: &'a mut usize,
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        *self.count += len;
+        Ok(len)
+    }
+}
+
+/// Blocking reader for the chunks received from the download task.
+struct ChunkChannel {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl ChunkChannel {
+    /// Get at least _len_ bytes of the buffered data, without consuming it.
+    ///
+    /// Less data is returned if the content is shorter.
+    fn peek(&mut self, len: usize) -> &[u8] {
+        while self.data.len() - self.pos < len {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => {
+                    self.data.drain(..self.pos);
+                    self.pos = 0;
+                    self.data.extend_from_slice(&chunk);
+                }
+                None => break,
+            }
+        }
+
+        &self.data[self.pos..]
+    }
+}
+
+impl Read for ChunkChannel {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos == self.data.len() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => {
+                    self.data = chunk;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.data.len() - self.pos);
+        buf[..len].copy_from_slice(&self.data[self.pos..(self.pos + len)]);
+        self.pos += len;
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::MemoryTransport;
+    use sha2::{Digest, Sha256};
+    use std::collections::HashMap;
+    use std::io::Write;
+
+    /// Get a Distro serving the data as _url_ in small chunks.
+    fn chunked_distro(url: &str, data: &[u8]) -> Distro {
+        let transport = MemoryTransport::new().with_chunk_size(3);
+        transport.set(url, data);
+
+        let mut distro = Distro::repo("memory://repo", "memory", crate::Key::NoSignatureCheck);
+        distro.transport = Arc::new(transport);
+        distro
+    }
+
+    fn link(url: &str, data: &[u8]) -> Link {
+        let mut hashes = HashMap::new();
+        hashes.insert(LinkHash::Sha256, format!("{:x}", Sha256::digest(data)));
+        Link {
+            url: url.to_string(),
+            size: data.len(),
+            hashes,
+        }
+    }
+
+    async fn collect(stream: &mut StanzaStream) -> Result<Vec<String>> {
+        let mut stanzas = Vec::new();
+        while let Some(stanza) = stream.next().await {
+            stanzas.push(stanza?);
+        }
+        Ok(stanzas)
+    }
+
+    const CONTENT: &str = "Package: a\nVersion: 1\n\n\nPackage: b\r\nDescription: b\n .\n more\n\n";
+
+    #[test]
+    fn split_stanzas() {
+        let stanzas: Vec<String> = Stanzas::new(CONTENT.as_bytes())
+            .map(|s| s.unwrap())
+            .collect();
+        assert_eq!(
+            stanzas,
+            vec![
+                "Package: a\nVersion: 1",
+                "Package: b\nDescription: b\n .\n more"
+            ]
+        );
+
+        assert_eq!(Stanzas::new("\n \n".as_bytes()).count(), 0);
+        assert_eq!(
+            Stanzas::new("Package: c".as_bytes())
+                .next()
+                .unwrap()
+                .unwrap(),
+            "Package: c"
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_compressed_index() {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(CONTENT.as_bytes()).unwrap();
+        let gz = gz.finish().unwrap();
+
+        for (url, data) in [
+            ("main/binary-amd64/Packages.gz", gz),
+            ("main/binary-amd64/Packages", CONTENT.as_bytes().to_vec()),
+        ] {
+            let link = link(url, &data);
+            let distro = chunked_distro(url, &data);
+            let mut stream = stream_compressed(&distro, &link, false, &[LinkHash::Sha256])
+                .await
+                .unwrap();
+            let stanzas = collect(&mut stream).await.unwrap();
+            assert_eq!(stanzas.len(), 2);
+            assert_eq!(stanzas[0], "Package: a\nVersion: 1");
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_hash_mismatch() {
+        let link = link("main/binary-amd64/Packages", b"Package: other\n");
+        let distro = chunked_distro("main/binary-amd64/Packages", CONTENT.as_bytes());
+
+        let mut stream = stream_compressed(&distro, &link, false, &[LinkHash::Sha256])
+            .await
+            .unwrap();
+        let err = collect(&mut stream).await.unwrap_err();
+        assert!(err.message().unwrap().contains("hash verification"));
+    }
+
+    #[tokio::test]
+    async fn stream_dropped() {
+        let data = CONTENT.repeat(1000).into_bytes();
+        let link = link("main/binary-amd64/Packages", &data);
+        let distro = chunked_distro("main/binary-amd64/Packages", &data);
+
+        let mut stream = stream_compressed(&distro, &link, false, &[LinkHash::Sha256])
+            .await
+            .unwrap();
+        stream.next().await.unwrap().unwrap();
+        drop(stream);
+    }
+}
@@ -0,0 +1,274 @@
+//! Helpers shared by the unit tests.
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::transport::{ChunkReader, Conditional, ConditionalReader, Transport, Validators};
+use crate::{Distro, Error, ErrorType, Key, Result};
+
+/// Get the path of a test data file.
+pub fn test_data(path: &str) -> String {
+    format!("{}/tests/data/{path}", env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Get the fingerprint stored in a test data file.
+pub fn test_fingerprint(name: &str) -> String {
+    fs::read_to_string(test_data(&format!("keys/{name}.fpr")))
+        .unwrap()
+        .trim()
+        .to_string()
+}
+
+/// Create an empty temporary folder for a test.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("libapt-{name}-{}", std::process::id()));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Builder for a local test repository providing the suite _local_.
+///
+/// The paths of the files are relative to the _dists/local_ folder.
+#[derive(Debug, Default)]
+pub struct TestRepo {
+    fields: Vec<String>,
+    files: Vec<(String, Vec<u8>)>,
+    hashes: Vec<(String, String, usize)>,
+}
+
+impl TestRepo {
+    pub fn new() -> TestRepo {
+        TestRepo::default()
+    }
+
+    /// Add a field to the InRelease file, e.g. _Acquire-By-Hash: yes_.
+    pub fn field(mut self, field: &str) -> TestRepo {
+        self.fields.push(field.to_string());
+        self
+    }
+
+    /// Add an index file, which is listed in the InRelease file.
+    pub fn index(self, path: &str, content: &[u8]) -> TestRepo {
+        self.listed(path, content).file(path, content)
+    }
+
+    /// List the SHA256 hash of the content in the InRelease file, without providing the file.
+    pub fn listed(mut self, path: &str, content: &[u8]) -> TestRepo {
+        let hash = format!("{:x}", Sha256::digest(content));
+        self.hashes.push((path.to_string(), hash, content.len()));
+        self
+    }
+
+    /// Add a file, which is not listed in the InRelease file.
+    pub fn file(mut self, path: &str, content: &[u8]) -> TestRepo {
+        self.files.push((path.to_string(), content.to_vec()));
+        self
+    }
+
+    /// Get the content of the InRelease file.
+    pub fn in_release(&self) -> String {
+        let mut in_release = "Suite: local\n".to_string();
+        for field in &self.fields {
+            in_release += &format!("{field}\n");
+        }
+        if !self.hashes.is_empty() {
+            in_release += "SHA256:\n";
+            for (path, hash, size) in &self.hashes {
+                in_release += &format!(" {hash} {size} {path}\n");
+            }
+        }
+        in_release
+    }
+
+    /// Write the repository to the root folder.
+    ///
+    /// Returns a Distro for the repository without signature check.
+    pub fn build(&self, root: &Path) -> Distro {
+        let dists = root.join("dists/local");
+        fs::create_dir_all(&dists).unwrap();
+        fs::write(dists.join("InRelease"), self.in_release()).unwrap();
+
+        for (path, content) in &self.files {
+            let path = dists.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        Distro::repo(root.to_str().unwrap(), "local", Key::NoSignatureCheck)
+    }
+}
+
+/// Transport serving in-memory files.
+///
+/// The transport records the requests and the maximum number of concurrent downloads.
+/// The ETag of a file is the SHA256 hash of its content.
+/// Downloads can be delayed, and opened files are provided in chunks of _chunk_size_ bytes.
+#[derive(Debug, Default)]
+pub struct MemoryTransport {
+    files: Mutex<HashMap<String, Vec<u8>>>,
+    requests: Mutex<Vec<(String, bool)>>,
+    active: AtomicUsize,
+    max_active: AtomicUsize,
+    delay: Duration,
+    chunk_size: usize,
+}
+
+impl MemoryTransport {
+    pub fn new() -> MemoryTransport {
+        MemoryTransport::default()
+    }
+
+    /// Delay each download by the given duration.
+    pub fn with_delay(mut self, delay: Duration) -> MemoryTransport {
+        self.delay = delay;
+        self
+    }
+
+    /// Provide opened files in chunks of the given size.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> MemoryTransport {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Set the content of the URL.
+    pub fn set(&self, url: &str, content: &[u8]) {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), content.to_vec());
+    }
+
+    /// Get the number of requests, including not modified conditional downloads.
+    pub fn requests(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+
+    /// Get the number of requests which transferred the content.
+    pub fn downloads(&self) -> usize {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, modified)| *modified)
+            .count()
+    }
+
+    /// Get the maximum number of concurrent downloads.
+    pub fn max_active(&self) -> usize {
+        self.max_active.load(Ordering::SeqCst)
+    }
+
+    fn content(&self, url: &str) -> Result<Vec<u8>> {
+        match self.files.lock().unwrap().get(url) {
+            Some(content) => Ok(content.clone()),
+            None => Err(Error::new(url, ErrorType::NotFound)),
+        }
+    }
+
+    /// Get a reader providing the data in chunks of _chunk_size_.
+    fn chunks(&self, data: Vec<u8>) -> Box<dyn ChunkReader> {
+        let chunk_size = match self.chunk_size {
+            0 => data.len().max(1),
+            chunk_size => chunk_size,
+        };
+        Box::new(MemoryChunks { data, chunk_size })
+    }
+
+    fn etag(content: &[u8]) -> String {
+        format!("\"{:x}\"", Sha256::digest(content))
+    }
+
+    /// Get the content of the URL, recording the request.
+    async fn fetch(&self, url: &str, validators: &Validators) -> Result<Conditional> {
+        let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_active.fetch_max(active, Ordering::SeqCst);
+        if !self.delay.is_zero() {
+            tokio::time::sleep(self.delay).await;
+        }
+        self.active.fetch_sub(1, Ordering::SeqCst);
+
+        let content = self.content(url)?;
+        let etag = MemoryTransport::etag(&content);
+        let modified = validators.etag.as_ref() != Some(&etag);
+        self.requests
+            .lock()
+            .unwrap()
+            .push((url.to_string(), modified));
+
+        if modified {
+            let validators = Validators {
+                etag: Some(etag),
+                last_modified: None,
+            };
+            Ok(Conditional::Modified(content, validators))
+        } else {
+            Ok(Conditional::NotModified)
+        }
+    }
+}
+
+/// ChunkReader providing in-memory content in chunks of a fixed size.
+struct MemoryChunks {
+    data: Vec<u8>,
+    chunk_size: usize,
+}
+
+#[async_trait]
+impl ChunkReader for MemoryChunks {
+    async fn chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.data.is_empty() {
+            return Ok(None);
+        }
+        let len = self.data.len().min(self.chunk_size);
+        Ok(Some(self.data.drain(..len).collect()))
+    }
+}
+
+#[async_trait]
+impl Transport for MemoryTransport {
+    async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        match self.fetch(url, &Validators::default()).await? {
+            Conditional::Modified(data, _) => Ok(data),
+            Conditional::NotModified => Err(Error::new(url, ErrorType::Download)),
+        }
+    }
+
+    async fn get_etag(&self, url: &str) -> Result<String> {
+        Ok(MemoryTransport::etag(&self.content(url)?))
+    }
+
+    async fn download_conditional(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> Result<Conditional> {
+        self.fetch(url, validators).await
+    }
+
+    async fn open(&self, url: &str) -> Result<Box<dyn ChunkReader>> {
+        let data = self.download(url).await?;
+        Ok(self.chunks(data))
+    }
+
+    async fn open_conditional(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> Result<ConditionalReader> {
+        match self.fetch(url, validators).await? {
+            Conditional::Modified(data, validators) => {
+                Ok(ConditionalReader::Modified(self.chunks(data), validators))
+            }
+            Conditional::NotModified => Ok(ConditionalReader::NotModified),
+        }
+    }
+}
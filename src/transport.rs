@@ -0,0 +1,877 @@
+//! Transport abstraction used for all downloads.
+
+#[cfg(not(test))]
+use log::{info, warn};
+
+#[cfg(test)]
+use std::{println as info, println as warn};
+
+use async_trait::async_trait;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::{Certificate, Client, Method, Proxy, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::time::sleep;
+
+use crate::auth::{find_credentials, Authentication, Credentials};
+use crate::util::{is_local, local_path};
+use crate::{Error, ErrorType, Result};
+
+/// A Transport fetches the content of URLs.
+///
+/// All downloads of libapt, i.e. InRelease files, package indices
+/// and signing keys, are done using the transport of the [crate::Distro].
+/// Implement this trait to plug in a custom fetcher,
+/// e.g. for an authenticated artifact store or in-memory test fixtures.
+#[async_trait]
+pub trait Transport: fmt::Debug + Send + Sync {
+    /// Download the content of the given URL.
+    ///
+    /// An error of type _ErrorType::NotFound_ shall be returned if the URL doesn't exist.
+    async fn download(&self, url: &str) -> Result<Vec<u8>>;
+
+    /// Get the etag of the given URL.
+    ///
+    /// This method is used to check if an URL exists.
+    async fn get_etag(&self, url: &str) -> Result<String>;
+
+    /// Check if the given URL exists.
+    ///
+    /// The default implementation uses [Transport::get_etag],
+    /// i.e. URLs without an etag are considered as missing.
+    async fn exists(&self, url: &str) -> Result<bool> {
+        Ok(self.get_etag(url).await.is_ok())
+    }
+
+    /// Download the content of the given URL if it was modified.
+    ///
+    /// The _validators_ of a previous download are used to revalidate the content,
+    /// e.g. using the HTTP headers _If-None-Match_ and _If-Modified-Since_.
+    /// The default implementation doesn't support revalidation,
+    /// and always downloads the full content.
+    async fn download_conditional(
+        &self,
+        url: &str,
+        _validators: &Validators,
+    ) -> Result<Conditional> {
+        let data = self.download(url).await?;
+        Ok(Conditional::Modified(data, Validators::default()))
+    }
+
+    /// Open the given URL for reading the content in chunks.
+    ///
+    /// This allows processing large files, e.g. package indices, with bounded memory.
+    /// The default implementation downloads the full content,
+    /// and provides it as a single chunk.
+    async fn open(&self, url: &str) -> Result<Box<dyn ChunkReader>> {
+        let data = self.download(url).await?;
+        Ok(Box::new(MemoryChunks(Some(data))))
+    }
+
+    /// Open the given URL for reading the content in chunks, if it was modified.
+    ///
+    /// This is the streaming variant of [Transport::download_conditional].
+    /// The default implementation uses [Transport::download_conditional],
+    /// and provides the content as a single chunk.
+    async fn open_conditional(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> Result<ConditionalReader> {
+        match self.download_conditional(url, validators).await? {
+            Conditional::Modified(data, validators) => Ok(ConditionalReader::Modified(
+                Box::new(MemoryChunks(Some(data))),
+                validators,
+            )),
+            Conditional::NotModified => Ok(ConditionalReader::NotModified),
+        }
+    }
+
+    /// Discard cached content of the given URL.
+    ///
+    /// This method is called if the content failed the verification,
+    /// to avoid that the same broken content is used again.
+    /// The default implementation does nothing.
+    fn evict(&self, _url: &str) {}
+}
+
+/// A ChunkReader provides the content of an opened URL in chunks.
+#[async_trait]
+pub trait ChunkReader: Send {
+    /// Get the next chunk of the content.
+    ///
+    /// None is returned at the end of the content.
+    async fn chunk(&mut self) -> Result<Option<Vec<u8>>>;
+}
+
+/// ChunkReader for content which is already in memory.
+struct MemoryChunks(Option<Vec<u8>>);
+
+#[async_trait]
+impl ChunkReader for MemoryChunks {
+    async fn chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.0.take())
+    }
+}
+
+/// ChunkReader for local files.
+pub(crate) struct FileChunks {
+    file: fs::File,
+    url: String,
+}
+
+impl FileChunks {
+    /// Open the file at _path_, the _url_ is used for error messages.
+    pub(crate) fn open(path: &str, url: &str) -> Result<FileChunks> {
+        let file = fs::File::open(path).map_err(|e| Error::from_io_error(e, url))?;
+        Ok(FileChunks {
+            file,
+            url: url.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl ChunkReader for FileChunks {
+    async fn chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut data = vec![0; CHUNK_SIZE];
+        let len = self
+            .file
+            .read(&mut data)
+            .map_err(|e| Error::from_io_error(e, &self.url))?;
+
+        if len == 0 {
+            return Ok(None);
+        }
+
+        data.truncate(len);
+        Ok(Some(data))
+    }
+}
+
+/// ChunkReader for the body of HTTP responses.
+struct ResponseChunks {
+    response: Response,
+    url: String,
+}
+
+#[async_trait]
+impl ChunkReader for ResponseChunks {
+    async fn chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        let chunk = self
+            .response
+            .chunk()
+            .await
+            .map_err(|e| Error::from_reqwest(e, &self.url))?;
+
+        Ok(chunk.map(|c| c.to_vec()))
+    }
+}
+
+/// Size of the chunks read from local files.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The Validators identify a version of downloaded content.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Validators {
+    /// Value of the _ETag_ HTTP header.
+    pub etag: Option<String>,
+    /// Value of the _Last-Modified_ HTTP header.
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Check if no validator is known.
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Result of a conditional download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conditional {
+    /// The content was modified, the new content and validators are provided.
+    Modified(Vec<u8>, Validators),
+    /// The content is unchanged.
+    NotModified,
+}
+
+/// Result of a conditional open.
+pub enum ConditionalReader {
+    /// The content was modified, a reader for the new content and the validators are provided.
+    Modified(Box<dyn ChunkReader>, Validators),
+    /// The content is unchanged.
+    NotModified,
+}
+
+/// Configuration of the HTTP client used by the [HttpTransport].
+///
+/// The client is created once and reused for all requests of the transport.
+/// Failed requests, i.e. connection errors, timeouts, server errors and
+/// "429 Too Many Requests" responses, are retried _retries_ times.
+/// The delay between the retries starts with _backoff_ and is doubled for each retry.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ClientConfig {
+    /// Timeout for the complete request, including the body.
+    pub timeout: Option<Duration>,
+    /// Timeout for establishing the connection.
+    pub connect_timeout: Option<Duration>,
+    /// Timeout for reading data from the connection.
+    pub read_timeout: Option<Duration>,
+    /// Number of retries for failed requests.
+    pub retries: u32,
+    /// Delay before the first retry.
+    pub backoff: Duration,
+    /// Proxy URL used for all requests.
+    pub proxy: Option<String>,
+    /// User agent sent with all requests.
+    pub user_agent: Option<String>,
+    /// Additional headers sent with all requests.
+    pub headers: HashMap<String, String>,
+    /// Path of a PEM CA bundle which is trusted in addition to the system CAs.
+    pub ca_bundle: Option<String>,
+    /// Credentials for HTTP authentication, e.g. read using [crate::read_auth_conf].
+    /// The first credentials matching the URL are used.
+    #[serde(default)]
+    pub credentials: Vec<Credentials>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> ClientConfig {
+        ClientConfig {
+            timeout: None,
+            connect_timeout: Some(Duration::from_secs(30)),
+            read_timeout: Some(Duration::from_secs(60)),
+            retries: 3,
+            backoff: Duration::from_millis(500),
+            proxy: None,
+            user_agent: Some(format!("libapt/{}", env!("CARGO_PKG_VERSION"))),
+            headers: HashMap::new(),
+            ca_bundle: None,
+            credentials: Vec::new(),
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Build the reqwest Client for this configuration.
+    fn build(&self) -> Result<Client> {
+        let mut builder = Client::builder();
+
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+
+        if let Some(proxy) = &self.proxy {
+            let proxy = Proxy::all(proxy).map_err(|e| {
+                Error::from_error(&e, ErrorType::ApiUsage, &format!("Invalid proxy {proxy}"))
+            })?;
+            builder = builder.proxy(proxy);
+        }
+
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let header = HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+                Error::from_error(&e, ErrorType::ApiUsage, &format!("Invalid header {name}"))
+            })?;
+            let value = HeaderValue::from_str(value).map_err(|e| {
+                Error::from_error(
+                    &e,
+                    ErrorType::ApiUsage,
+                    &format!("Invalid value of header {name}"),
+                )
+            })?;
+            headers.insert(header, value);
+        }
+        builder = builder.default_headers(headers);
+
+        if let Some(ca_bundle) = &self.ca_bundle {
+            let pem = fs::read(ca_bundle).map_err(|e| {
+                Error::from_error(
+                    &e,
+                    ErrorType::ApiUsage,
+                    &format!("Reading CA bundle {ca_bundle} failed"),
+                )
+            })?;
+            let certificates = Certificate::from_pem_bundle(&pem).map_err(|e| {
+                Error::from_error(
+                    &e,
+                    ErrorType::ApiUsage,
+                    &format!("Invalid CA bundle {ca_bundle}"),
+                )
+            })?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        builder
+            .build()
+            .map_err(|e| Error::from_error(&e, ErrorType::ApiUsage, "Creating HTTP client failed"))
+    }
+}
+
+/// The default transport using reqwest for HTTP URLs.
+///
+/// Local URLs, i.e. _file://_ URLs and plain paths, are read from the filesystem.
+/// The HTTP client is shared by all clones of the transport.
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    client: Client,
+    retries: u32,
+    backoff: Duration,
+    credentials: Arc<Vec<Credentials>>,
+}
+
+impl Default for HttpTransport {
+    fn default() -> HttpTransport {
+        HttpTransport::new()
+    }
+}
+
+impl HttpTransport {
+    /// Create a new HTTP transport using the default [ClientConfig].
+    pub fn new() -> HttpTransport {
+        HttpTransport::with_config(&ClientConfig::default())
+            .expect("The default client configuration is valid.")
+    }
+
+    /// Create a new HTTP transport using the given [ClientConfig].
+    pub fn with_config(config: &ClientConfig) -> Result<HttpTransport> {
+        Ok(HttpTransport {
+            client: config.build()?,
+            retries: config.retries,
+            backoff: config.backoff,
+            credentials: Arc::new(config.credentials.clone()),
+        })
+    }
+
+    /// Send a request, and retry it in case of transient failures.
+    async fn send(&self, method: Method, url: &str, headers: HeaderMap) -> Result<Response> {
+        let mut attempt = 0;
+
+        let credentials = find_credentials(&self.credentials, url);
+
+        loop {
+            let mut request = self
+                .client
+                .request(method.clone(), url)
+                .headers(headers.clone());
+
+            match credentials.map(|c| &c.authentication) {
+                Some(Authentication::Basic { login, password }) => {
+                    request = request.basic_auth(login, Some(password));
+                }
+                Some(Authentication::Bearer { token }) => {
+                    request = request.bearer_auth(token);
+                }
+                None => {}
+            }
+
+            let result = request.send().await;
+
+            let retry = match &result {
+                Ok(response) => {
+                    response.status().is_server_error()
+                        || response.status() == StatusCode::TOO_MANY_REQUESTS
+                }
+                Err(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+            };
+
+            if !retry || attempt >= self.retries {
+                return result.map_err(|e| Error::from_reqwest(e, url));
+            }
+
+            let delay = self.backoff * 2u32.saturating_pow(attempt);
+            warn!("Request for {url} failed, retrying in {delay:?}.");
+            sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Get the etag of a local file.
+    ///
+    /// The modification time and the size are used as etag.
+    fn local_etag(url: &str) -> Result<String> {
+        let metadata = fs::metadata(local_path(url)).map_err(|e| Error::from_io_error(e, url))?;
+
+        if !metadata.is_file() {
+            return Err(Error::new(
+                &format!("Url {url} is not a file!"),
+                ErrorType::Download,
+            ));
+        }
+
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        Ok(format!("{:x}-{:x}", modified, metadata.len()))
+    }
+
+    /// Get the error for a failed request.
+    ///
+    /// The error type is _NotFound_ for missing URLs, else _Download_.
+    fn status_error(url: &str, status: StatusCode) -> Error {
+        let error_type = if status == StatusCode::NOT_FOUND || status == StatusCode::GONE {
+            ErrorType::NotFound
+        } else {
+            ErrorType::Download
+        };
+        Error::new(&format!("Url {url} download failed! {status}"), error_type)
+    }
+
+    /// Get the value of a response header as String.
+    fn header(response: &Response, name: HeaderName) -> Option<String> {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    }
+
+    /// Send a conditional GET request using the _validators_.
+    ///
+    /// Returns None if the content is not modified,
+    /// else the response and the validators of the new content.
+    async fn send_conditional(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> Result<Option<(Response, Validators)>> {
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = validators
+            .etag
+            .as_ref()
+            .and_then(|e| HeaderValue::from_str(e).ok())
+        {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = validators
+            .last_modified
+            .as_ref()
+            .and_then(|l| HeaderValue::from_str(l).ok())
+        {
+            headers.insert(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        let response = self.send(Method::GET, url, headers).await?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            info!("Url {url} is not modified.");
+            return Ok(None);
+        }
+
+        if !response.status().is_success() {
+            return Err(HttpTransport::status_error(url, response.status()));
+        }
+
+        let validators = Validators {
+            etag: HttpTransport::header(&response, ETAG),
+            last_modified: HttpTransport::header(&response, LAST_MODIFIED),
+        };
+
+        Ok(Some((response, validators)))
+    }
+}
+
+#[async_trait]
+impl Transport for HttpTransport {
+    async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        if is_local(url) {
+            info!("Reading local file {url}.");
+            return fs::read(local_path(url)).map_err(|e| Error::from_io_error(e, url));
+        }
+
+        let response = self.send(Method::GET, url, HeaderMap::new()).await?;
+
+        if !response.status().is_success() {
+            return Err(HttpTransport::status_error(url, response.status()));
+        }
+
+        let data = response
+            .bytes()
+            .await
+            .map_err(|e| Error::from_reqwest(e, url))?;
+
+        Ok(data.to_vec())
+    }
+
+    async fn get_etag(&self, url: &str) -> Result<String> {
+        if is_local(url) {
+            return HttpTransport::local_etag(url);
+        }
+
+        let response = self.send(Method::HEAD, url, HeaderMap::new()).await?;
+
+        if !response.status().is_success() {
+            return Err(HttpTransport::status_error(url, response.status()));
+        }
+
+        let etag = match response.headers().get("etag") {
+            Some(etag) => etag,
+            None => {
+                return Err(Error::new(
+                    &format!("No etag found in header of {url}!"),
+                    ErrorType::Download,
+                ));
+            }
+        };
+
+        let etag = etag
+            .to_str()
+            .map_err(|e| Error::from_to_str_error(e, url))?;
+
+        Ok(etag.to_string())
+    }
+
+    async fn exists(&self, url: &str) -> Result<bool> {
+        if is_local(url) {
+            return Ok(fs::metadata(local_path(url))
+                .map(|m| m.is_file())
+                .unwrap_or(false));
+        }
+
+        let response = self.send(Method::HEAD, url, HeaderMap::new()).await?;
+        Ok(response.status().is_success())
+    }
+
+    async fn download_conditional(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> Result<Conditional> {
+        if is_local(url) {
+            let etag = HttpTransport::local_etag(url)?;
+            if validators.etag.as_ref() == Some(&etag) {
+                return Ok(Conditional::NotModified);
+            }
+
+            let data = self.download(url).await?;
+            let validators = Validators {
+                etag: Some(etag),
+                last_modified: None,
+            };
+            return Ok(Conditional::Modified(data, validators));
+        }
+
+        let (response, validators) = match self.send_conditional(url, validators).await? {
+            Some(response) => response,
+            None => return Ok(Conditional::NotModified),
+        };
+
+        let data = response
+            .bytes()
+            .await
+            .map_err(|e| Error::from_reqwest(e, url))?;
+
+        Ok(Conditional::Modified(data.to_vec(), validators))
+    }
+
+    async fn open(&self, url: &str) -> Result<Box<dyn ChunkReader>> {
+        if is_local(url) {
+            info!("Reading local file {url}.");
+            return Ok(Box::new(FileChunks::open(local_path(url), url)?));
+        }
+
+        let response = self.send(Method::GET, url, HeaderMap::new()).await?;
+
+        if !response.status().is_success() {
+            return Err(HttpTransport::status_error(url, response.status()));
+        }
+
+        Ok(Box::new(ResponseChunks {
+            response,
+            url: url.to_string(),
+        }))
+    }
+
+    async fn open_conditional(
+        &self,
+        url: &str,
+        validators: &Validators,
+    ) -> Result<ConditionalReader> {
+        if is_local(url) {
+            let etag = HttpTransport::local_etag(url)?;
+            if validators.etag.as_ref() == Some(&etag) {
+                return Ok(ConditionalReader::NotModified);
+            }
+
+            let validators = Validators {
+                etag: Some(etag),
+                last_modified: None,
+            };
+            return Ok(ConditionalReader::Modified(
+                self.open(url).await?,
+                validators,
+            ));
+        }
+
+        let (response, validators) = match self.send_conditional(url, validators).await? {
+            Some(response) => response,
+            None => return Ok(ConditionalReader::NotModified),
+        };
+
+        Ok(ConditionalReader::Modified(
+            Box::new(ResponseChunks {
+                response,
+                url: url.to_string(),
+            }),
+            validators,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{temp_dir, MemoryTransport};
+    use crate::{Architecture, Distro, Key, PackageIndex, Release};
+    use sha2::{Digest, Sha256};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn custom_transport() {
+        let packages = "Package: hello\nVersion: 2.10-2\nMaintainer: Santiago Vila <sanvila@debian.org>\nFilename: pool/main/h/hello/hello_2.10-2_amd64.deb\nSize: 56132\nDescription: example package based on GNU hello\n";
+        let hash = format!("{:x}", Sha256::digest(packages.as_bytes()));
+        let in_release = format!(
+            "Suite: memory\nArchitectures: amd64\nComponents: main\nSHA256:\n {hash} {} main/binary-amd64/Packages\n",
+            packages.len()
+        );
+
+        let transport = MemoryTransport::new();
+        transport.set(
+            "memory://repo/dists/memory/InRelease",
+            in_release.as_bytes(),
+        );
+        transport.set(
+            "memory://repo/dists/memory/main/binary-amd64/Packages",
+            packages.as_bytes(),
+        );
+
+        let mut distro = Distro::repo("memory://repo", "memory", Key::NoSignatureCheck);
+        distro.transport = Arc::new(transport);
+
+        let release = Release::from_distro(&distro).await.unwrap();
+        assert_eq!(release.suite, Some("memory".to_string()));
+
+        let package_index = PackageIndex::new(&release, "main", &Architecture::Amd64)
+            .await
+            .unwrap();
+        assert_eq!(package_index.package_count(), 1);
+        assert!(package_index.get("hello", None).is_some());
+    }
+
+    #[tokio::test]
+    async fn missing_local_file() {
+        let transport = HttpTransport::new();
+        assert!(transport
+            .download("/libapt/missing/InRelease")
+            .await
+            .is_err());
+        assert!(transport
+            .get_etag("/libapt/missing/InRelease")
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn client_config() {
+        let mut config = ClientConfig::default();
+        config.proxy = Some("http://proxy.example.com:3128".to_string());
+        config
+            .headers
+            .insert("X-Api-Key".to_string(), "secret".to_string());
+        HttpTransport::with_config(&config).unwrap();
+
+        let mut config = ClientConfig::default();
+        config
+            .headers
+            .insert("Invalid Header".to_string(), "value".to_string());
+        assert!(HttpTransport::with_config(&config).is_err());
+
+        let mut config = ClientConfig::default();
+        config.ca_bundle = Some("/libapt/missing/ca.pem".to_string());
+        assert!(HttpTransport::with_config(&config).is_err());
+    }
+
+    #[tokio::test]
+    async fn retry_server_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/InRelease", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for status in ["503 Service Unavailable", "200 OK"] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buffer = vec![0; 4096];
+                let size = socket.read(&mut buffer).await.unwrap();
+                requests.push(String::from_utf8_lossy(&buffer[..size]).to_string());
+
+                let response = format!(
+                    "HTTP/1.1 {status}\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello"
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        let mut config = ClientConfig::default();
+        config.backoff = Duration::from_millis(10);
+        config.user_agent = Some("libapt-test".to_string());
+        let transport = HttpTransport::with_config(&config).unwrap();
+
+        let content = transport.download(&url).await.unwrap();
+        assert_eq!(content, b"hello");
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1]
+            .to_lowercase()
+            .contains("user-agent: libapt-test"));
+    }
+
+    #[tokio::test]
+    async fn authentication() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buffer = vec![0; 4096];
+                let size = socket.read(&mut buffer).await.unwrap();
+                requests.push(String::from_utf8_lossy(&buffer[..size]).to_lowercase());
+
+                let response =
+                    "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello";
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        let mut config = ClientConfig::default();
+        config.credentials = vec![
+            Credentials::bearer(&format!("http://{address}/private"), "token"),
+            Credentials::basic(&format!("http://{address}"), "user", "secret"),
+        ];
+        let transport = HttpTransport::with_config(&config).unwrap();
+
+        transport
+            .download(&format!("http://{address}/private/InRelease"))
+            .await
+            .unwrap();
+        transport
+            .download(&format!("http://{address}/public/InRelease"))
+            .await
+            .unwrap();
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].contains("authorization: bearer token"));
+        // base64 of "user:secret"
+        assert!(requests[1].contains("authorization: basic dxnlcjpzzwnyzxq="));
+    }
+
+    #[tokio::test]
+    async fn conditional_download() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/InRelease", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buffer = vec![0; 4096];
+                let size = socket.read(&mut buffer).await.unwrap();
+                let request = String::from_utf8_lossy(&buffer[..size]).to_lowercase();
+
+                let response = if request.contains("if-none-match: \"v1\"") {
+                    "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_string()
+                } else {
+                    "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nLast-Modified: Mon, 01 Jan 2024 00:00:00 GMT\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello".to_string()
+                };
+                socket.write_all(response.as_bytes()).await.unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+
+        let transport = HttpTransport::new();
+
+        let validators = match transport
+            .download_conditional(&url, &Validators::default())
+            .await
+            .unwrap()
+        {
+            Conditional::Modified(data, validators) => {
+                assert_eq!(data, b"hello");
+                validators
+            }
+            Conditional::NotModified => panic!("Content must be downloaded."),
+        };
+        assert_eq!(validators.etag, Some("\"v1\"".to_string()));
+        assert_eq!(
+            validators.last_modified,
+            Some("Mon, 01 Jan 2024 00:00:00 GMT".to_string())
+        );
+
+        let result = transport
+            .download_conditional(&url, &validators)
+            .await
+            .unwrap();
+        assert_eq!(result, Conditional::NotModified);
+
+        let requests = server.await.unwrap();
+        assert!(requests[1].contains("if-modified-since: mon, 01 jan 2024 00:00:00 gmt"));
+    }
+
+    #[tokio::test]
+    async fn conditional_local_file() {
+        let dir = temp_dir("conditional");
+        let path = dir.join("InRelease");
+        fs::write(&path, "Suite: local\n").unwrap();
+        let url = format!("file://{}", path.to_str().unwrap());
+
+        let transport = HttpTransport::new();
+        let validators = match transport
+            .download_conditional(&url, &Validators::default())
+            .await
+            .unwrap()
+        {
+            Conditional::Modified(_, validators) => validators,
+            Conditional::NotModified => panic!("Content must be read."),
+        };
+        assert!(!validators.is_empty());
+
+        let result = transport
+            .download_conditional(&url, &validators)
+            .await
+            .unwrap();
+        assert_eq!(result, Conditional::NotModified);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
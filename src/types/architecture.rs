@@ -2,6 +2,84 @@ use crate::Result;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+/// CPU names of the dpkg _cputable_.
+const CPUS: [&str; 36] = [
+    "i386",
+    "ia64",
+    "alpha",
+    "amd64",
+    "arc",
+    "armeb",
+    "arm",
+    "arm64",
+    "avr32",
+    "hppa",
+    "loong64",
+    "m32r",
+    "m68k",
+    "mips",
+    "mipsel",
+    "mipsr6",
+    "mipsr6el",
+    "mips64",
+    "mips64el",
+    "mips64r6",
+    "mips64r6el",
+    "nios2",
+    "or1k",
+    "powerpc",
+    "powerpcel",
+    "ppc64",
+    "ppc64el",
+    "riscv64",
+    "s390",
+    "s390x",
+    "sh3",
+    "sh3eb",
+    "sh4",
+    "sh4eb",
+    "sparc",
+    "sparc64",
+];
+
+/// Tuples _abi-libc-os-cpu_ and architecture names of the dpkg _tupletable_.
+///
+/// The placeholder _<cpu>_ stands for all [CPUS].
+/// The first matching entry is used, i.e. explicit entries take precedence.
+const TUPLES: [(&str, &str); 31] = [
+    ("eabihf-musl-linux-arm", "musl-linux-armhf"),
+    ("base-musl-linux-<cpu>", "musl-linux-<cpu>"),
+    ("ilp32-gnu-linux-arm64", "arm64ilp32"),
+    ("eabihf-gnu-linux-arm", "armhf"),
+    ("eabi-gnu-linux-arm", "armel"),
+    ("abin32-gnu-linux-mips64r6el", "mipsn32r6el"),
+    ("abin32-gnu-linux-mips64r6", "mipsn32r6"),
+    ("abin32-gnu-linux-mips64el", "mipsn32el"),
+    ("abin32-gnu-linux-mips64", "mipsn32"),
+    ("abi64-gnu-linux-mips64r6el", "mips64r6el"),
+    ("abi64-gnu-linux-mips64r6", "mips64r6"),
+    ("abi64-gnu-linux-mips64el", "mips64el"),
+    ("abi64-gnu-linux-mips64", "mips64"),
+    ("spe-gnu-linux-powerpc", "powerpcspe"),
+    ("x32-gnu-linux-amd64", "x32"),
+    ("base-gnu-linux-<cpu>", "<cpu>"),
+    ("eabihf-gnu-kfreebsd-arm", "kfreebsd-armhf"),
+    ("base-gnu-kfreebsd-<cpu>", "kfreebsd-<cpu>"),
+    ("base-gnu-knetbsd-<cpu>", "knetbsd-<cpu>"),
+    ("base-gnu-kopensolaris-<cpu>", "kopensolaris-<cpu>"),
+    ("base-gnu-hurd-<cpu>", "hurd-<cpu>"),
+    ("base-bsd-dragonflybsd-<cpu>", "dragonflybsd-<cpu>"),
+    ("base-bsd-freebsd-<cpu>", "freebsd-<cpu>"),
+    ("base-bsd-openbsd-<cpu>", "openbsd-<cpu>"),
+    ("base-bsd-netbsd-<cpu>", "netbsd-<cpu>"),
+    ("base-bsd-darwin-<cpu>", "darwin-<cpu>"),
+    ("base-sysv-aix-<cpu>", "aix-<cpu>"),
+    ("base-sysv-solaris-<cpu>", "solaris-<cpu>"),
+    ("eabi-uclibc-linux-arm", "uclibc-linux-armel"),
+    ("base-uclibc-linux-<cpu>", "uclibc-linux-<cpu>"),
+    ("base-tos-mint-m68k", "mint-m68k"),
+];
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Deserialize, Serialize)]
 pub enum Architecture {
     Amd64,
@@ -21,10 +99,11 @@ pub enum Architecture {
 impl Architecture {
     pub fn from_str(arch: &str) -> Result<Architecture> {
         let arch = arch.to_lowercase();
-        let arch = if arch.starts_with("linux-") {
-            &arch[6..].trim()
-        } else {
-            arch.trim()
+        let arch = arch.trim();
+        // Wildcards like linux-any are kept, the prefix linux- of other names is removed.
+        let arch = match arch.strip_prefix("linux-") {
+            Some(name) if !is_wildcard(arch) => name.trim(),
+            _ => arch,
         };
 
         if arch == "amd64" {
@@ -53,6 +132,50 @@ impl Architecture {
 
         Ok(Architecture::Other(arch.to_string()))
     }
+
+    /// Get the Debian name of the architecture, e.g. _amd64_.
+    pub fn name(&self) -> String {
+        match self {
+            Architecture::Other(name) => name.clone(),
+            _ => self.to_string(),
+        }
+    }
+
+    /// Get the dpkg tuple _abi-libc-os-cpu_ of the architecture, e.g. _base-gnu-linux-amd64_.
+    ///
+    /// Returns None for unknown architectures, wildcards, _all_ and _source_.
+    pub fn tuple(&self) -> Option<[String; 4]> {
+        debtuple(&self.name())
+    }
+
+    /// Check if the architecture is a wildcard, e.g. _any_, _linux-any_ or _any-arm64_.
+    pub fn is_wildcard(&self) -> bool {
+        is_wildcard(&self.name())
+    }
+
+    /// Check if the architecture matches the given architecture name or wildcard,
+    /// like _dpkg-architecture --is_.
+    ///
+    /// The wildcard _any_ matches all architectures. Other wildcards are matched
+    /// using the dpkg tuples, where _any_ matches each part, e.g. _linux-any_
+    /// is _any-any-linux-any_, _musl-linux-any_ is _any-musl-linux-any_,
+    /// and _any-arm64_ is _any-any-any-arm64_.
+    pub fn matches(&self, pattern: &str) -> bool {
+        let name = self.name();
+        let pattern = pattern.trim().to_lowercase();
+
+        if pattern == "any" || pattern == name {
+            return true;
+        }
+
+        match (debtuple(&name), wildcard_tuple(&pattern)) {
+            (Some(tuple), Some(wildcard)) => tuple
+                .iter()
+                .zip(wildcard.iter())
+                .all(|(part, wildcard)| wildcard == "any" || part == wildcard),
+            _ => false,
+        }
+    }
 }
 
 impl fmt::Display for Architecture {
@@ -75,3 +198,148 @@ impl fmt::Display for Architecture {
         write!(f, "{}", name)
     }
 }
+
+/// Check if the architecture name is a wildcard, i.e. contains the part _any_.
+fn is_wildcard(name: &str) -> bool {
+    name.split('-').any(|part| part == "any")
+}
+
+/// Get the dpkg tuple of an architecture name, using the [TUPLES].
+fn debtuple(name: &str) -> Option<[String; 4]> {
+    for (tuple, arch) in TUPLES {
+        let cpu = match arch.split_once("<cpu>") {
+            Some((prefix, suffix)) => match name
+                .strip_prefix(prefix)
+                .and_then(|name| name.strip_suffix(suffix))
+            {
+                Some(cpu) if CPUS.contains(&cpu) => cpu,
+                _ => continue,
+            },
+            None if arch == name => "",
+            None => continue,
+        };
+
+        let tuple = tuple.replace("<cpu>", cpu);
+        let parts: Vec<&str> = tuple.splitn(4, '-').collect();
+        return Some([
+            parts[0].to_string(),
+            parts[1].to_string(),
+            parts[2].to_string(),
+            parts[3].to_string(),
+        ]);
+    }
+
+    None
+}
+
+/// Get the dpkg tuple of a wildcard, where missing leading parts are _any_.
+///
+/// Names which are no wildcards are converted using [debtuple].
+fn wildcard_tuple(pattern: &str) -> Option<[String; 4]> {
+    if !is_wildcard(pattern) {
+        return debtuple(pattern);
+    }
+
+    let parts: Vec<&str> = pattern.split('-').collect();
+    if parts.len() > 4 {
+        return None;
+    }
+
+    let mut tuple = [
+        "any".to_string(),
+        "any".to_string(),
+        "any".to_string(),
+        "any".to_string(),
+    ];
+    if parts.len() > 1 {
+        for (i, part) in parts.iter().enumerate() {
+            tuple[4 - parts.len() + i] = part.to_string();
+        }
+    }
+
+    Some(tuple)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn architecture_tuples() {
+        let tuple = |arch: &str| {
+            Architecture::from_str(arch)
+                .unwrap()
+                .tuple()
+                .map(|t| t.join("-"))
+        };
+        assert_eq!(tuple("amd64"), Some("base-gnu-linux-amd64".to_string()));
+        assert_eq!(tuple("armhf"), Some("eabihf-gnu-linux-arm".to_string()));
+        assert_eq!(tuple("x32"), Some("x32-gnu-linux-amd64".to_string()));
+        assert_eq!(
+            tuple("mips64el"),
+            Some("abi64-gnu-linux-mips64el".to_string())
+        );
+        assert_eq!(
+            tuple("musl-linux-arm64"),
+            Some("base-musl-linux-arm64".to_string())
+        );
+        assert_eq!(
+            tuple("kfreebsd-amd64"),
+            Some("base-gnu-kfreebsd-amd64".to_string())
+        );
+        assert_eq!(tuple("all"), None);
+        assert_eq!(tuple("linux-any"), None);
+        assert_eq!(tuple("unknown"), None);
+
+        assert_eq!(
+            Architecture::from_str("linux-any").unwrap(),
+            Architecture::Other("linux-any".to_string())
+        );
+        assert_eq!(
+            Architecture::from_str("linux-amd64").unwrap(),
+            Architecture::Amd64
+        );
+    }
+
+    #[test]
+    fn wildcard_matching() {
+        let arch = |arch: &str| Architecture::from_str(arch).unwrap();
+
+        for (architecture, pattern, expected) in [
+            ("riscv64", "any", true),
+            ("riscv64", "riscv64", true),
+            ("riscv64", "linux-any", true),
+            ("riscv64", "any-riscv64", true),
+            ("riscv64", "any-amd64", false),
+            ("riscv64", "kfreebsd-any", false),
+            ("riscv64", "all", false),
+            ("armhf", "any-arm", true),
+            ("armhf", "armel", false),
+            ("armhf", "gnueabihf-any", false),
+            ("armhf", "eabihf-any-any-any", true),
+            ("x32", "any-amd64", true),
+            ("x32", "amd64", false),
+            ("kfreebsd-amd64", "linux-any", false),
+            ("kfreebsd-amd64", "kfreebsd-any", true),
+            ("kfreebsd-amd64", "any-amd64", true),
+            ("musl-linux-arm64", "musl-linux-any", true),
+            ("musl-linux-arm64", "linux-any", true),
+            ("arm64", "musl-linux-any", false),
+            ("hurd-i386", "hurd-any", true),
+            ("hurd-i386", "gnu-any-any", true),
+            ("darwin-amd64", "gnu-any-any", false),
+            ("all", "any", true),
+            ("all", "linux-any", false),
+        ] {
+            assert_eq!(
+                arch(architecture).matches(pattern),
+                expected,
+                "{architecture} {pattern}"
+            );
+        }
+
+        assert!(arch("linux-any").is_wildcard());
+        assert!(arch("any").is_wildcard());
+        assert!(!arch("amd64").is_wildcard());
+    }
+}
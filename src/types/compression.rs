@@ -0,0 +1,204 @@
+use crate::{Error, ErrorType, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Read;
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use lz4_flex::frame::FrameDecoder;
+use lzma::LzmaReader;
+use ruzstd::StreamingDecoder;
+
+/// Compression formats of index files.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Deserialize, Serialize)]
+pub enum Compression {
+    Xz,
+    Zstd,
+    Gzip,
+    Bzip2,
+    Lz4,
+    // uncompressed
+    None,
+}
+
+impl Compression {
+    /// Default order of compressions, from best to no compression.
+    pub fn default_order() -> Vec<Compression> {
+        vec![
+            Compression::Xz,
+            Compression::Zstd,
+            Compression::Gzip,
+            Compression::Bzip2,
+            Compression::Lz4,
+            Compression::None,
+        ]
+    }
+
+    /// File extension of the compression, including the dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Xz => ".xz",
+            Compression::Zstd => ".zst",
+            Compression::Gzip => ".gz",
+            Compression::Bzip2 => ".bz2",
+            Compression::Lz4 => ".lz4",
+            Compression::None => "",
+        }
+    }
+
+    /// Guess the compression using the extension of the URL.
+    pub fn from_extension(url: &str) -> Compression {
+        [
+            Compression::Xz,
+            Compression::Zstd,
+            Compression::Gzip,
+            Compression::Bzip2,
+            Compression::Lz4,
+        ]
+        .into_iter()
+        .find(|c| url.ends_with(c.extension()))
+        .unwrap_or(Compression::None)
+    }
+
+    /// Detect the compression using the magic bytes of the data.
+    ///
+    /// Returns None if no known magic bytes are found.
+    pub fn from_magic(data: &[u8]) -> Option<Compression> {
+        if data.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else if data.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+            Some(Compression::Zstd)
+        } else if data.starts_with(&[0x1F, 0x8B]) {
+            Some(Compression::Gzip)
+        } else if data.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else if data.starts_with(&[0x04, 0x22, 0x4D, 0x18]) {
+            Some(Compression::Lz4)
+        } else {
+            None
+        }
+    }
+
+    /// Wrap the reader with a decoder for the compression.
+    ///
+    /// The _url_ is only used for error messages.
+    pub fn reader<'a, R: Read + 'a>(&self, inner: R, url: &str) -> Result<Box<dyn Read + 'a>> {
+        let reader: Box<dyn Read + 'a> = match self {
+            Compression::Xz => {
+                Box::new(LzmaReader::new_decompressor(inner).map_err(|e| Error::from_lzma(e, url))?)
+            }
+            Compression::Zstd => Box::new(StreamingDecoder::new(inner).map_err(|e| {
+                Error::new(
+                    &format!("Invalid zstd data of {url}: {e}"),
+                    ErrorType::Download,
+                )
+            })?),
+            Compression::Gzip => Box::new(MultiGzDecoder::new(inner)),
+            Compression::Bzip2 => Box::new(MultiBzDecoder::new(inner)),
+            Compression::Lz4 => Box::new(FrameDecoder::new(inner)),
+            Compression::None => Box::new(inner),
+        };
+
+        Ok(reader)
+    }
+
+    /// Decompress the data.
+    ///
+    /// The _url_ is only used for error messages.
+    pub fn decompress(&self, data: &[u8], url: &str) -> Result<Vec<u8>> {
+        if self == &Compression::Xz {
+            return lzma::decompress(data).map_err(|e| Error::from_lzma(e, url));
+        }
+
+        let mut content = Vec::new();
+        self.reader(data, url)?
+            .read_to_end(&mut content)
+            .map_err(|e| Error::from_io_error(e, url))?;
+
+        Ok(content)
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Compression::Xz => "xz",
+            Compression::Zstd => "zstd",
+            Compression::Gzip => "gzip",
+            Compression::Bzip2 => "bzip2",
+            Compression::Lz4 => "lz4",
+            Compression::None => "none",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const CONTENT: &[u8] = b"Package: hello\nVersion: 2.10-2\n";
+
+    #[test]
+    fn from_extension() {
+        assert_eq!(
+            Compression::from_extension("a/Packages.xz"),
+            Compression::Xz
+        );
+        assert_eq!(
+            Compression::from_extension("a/Packages.zst"),
+            Compression::Zstd
+        );
+        assert_eq!(
+            Compression::from_extension("a/Packages.gz"),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::from_extension("a/Packages.bz2"),
+            Compression::Bzip2
+        );
+        assert_eq!(
+            Compression::from_extension("a/Packages.lz4"),
+            Compression::Lz4
+        );
+        assert_eq!(Compression::from_extension("a/Packages"), Compression::None);
+    }
+
+    #[test]
+    fn decompress_formats() {
+        let xz = lzma::compress(CONTENT, 6).unwrap();
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(CONTENT).unwrap();
+        let gz = gz.finish().unwrap();
+
+        let mut bz2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bz2.write_all(CONTENT).unwrap();
+        let bz2 = bz2.finish().unwrap();
+
+        let mut lz4 = lz4_flex::frame::FrameEncoder::new(Vec::new());
+        lz4.write_all(CONTENT).unwrap();
+        let lz4 = lz4.finish().unwrap();
+
+        // zstd frame with a single raw block.
+        let mut zstd = vec![0x28, 0xB5, 0x2F, 0xFD, 0x20, CONTENT.len() as u8];
+        let header = ((CONTENT.len() as u32) << 3) | 1;
+        zstd.extend_from_slice(&header.to_le_bytes()[..3]);
+        zstd.extend_from_slice(CONTENT);
+
+        for (data, compression) in [
+            (xz, Compression::Xz),
+            (zstd, Compression::Zstd),
+            (gz, Compression::Gzip),
+            (bz2, Compression::Bzip2),
+            (lz4, Compression::Lz4),
+        ] {
+            assert_eq!(Compression::from_magic(&data), Some(compression.clone()));
+            assert_eq!(compression.decompress(&data, "test").unwrap(), CONTENT);
+        }
+
+        assert_eq!(Compression::from_magic(CONTENT), None);
+        assert!(Compression::Zstd.decompress(CONTENT, "test").is_err());
+    }
+}
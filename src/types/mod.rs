@@ -1,2 +1,4 @@
 pub mod architecture;
+pub mod compression;
+pub mod multi_arch;
 pub mod priority;
@@ -0,0 +1,42 @@
+use crate::{Error, ErrorType, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// The MultiArch value of a binary package,
+/// see [MultiArch spec](https://wiki.ubuntu.com/MultiarchSpec).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Deserialize, Serialize)]
+pub enum MultiArch {
+    // package is co-installable with itself for other architectures
+    Same,
+    // package satisfies dependencies of packages of all architectures
+    Foreign,
+    // package satisfies dependencies with the qualifier _:any_ of all architectures
+    Allowed,
+    // default, package only satisfies dependencies of the same architecture
+    #[default]
+    No,
+}
+
+impl FromStr for MultiArch {
+    type Err = Error;
+
+    fn from_str(multi_arch: &str) -> Result<MultiArch> {
+        let multi_arch = multi_arch.to_lowercase();
+        let multi_arch = multi_arch.trim();
+
+        if multi_arch == "same" {
+            return Ok(MultiArch::Same);
+        } else if multi_arch == "foreign" {
+            return Ok(MultiArch::Foreign);
+        } else if multi_arch == "allowed" {
+            return Ok(MultiArch::Allowed);
+        } else if multi_arch == "no" {
+            return Ok(MultiArch::No);
+        }
+
+        Err(Error::new(
+            &format!("Multi-Arch value {multi_arch} is not known!"),
+            ErrorType::UnknownMultiArch,
+        ))
+    }
+}
@@ -1,167 +1,355 @@
 //! Helper functions.
 
 #[cfg(not(test))]
-use log::{error, info};
+use log::{error, info, warn};
 
 #[cfg(test)]
-use std::{println as info, println as error};
+use std::{println as info, println as error, println as warn};
 
-use std::{collections::HashMap, io::Read};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
 
-use flate2::bufread::GzDecoder;
-use lzma;
 use md5;
-use reqwest::Client;
 use sha1::Sha1;
 use sha2::{Digest, Sha256, Sha512};
 
-use crate::{Error, Link, LinkHash, PackageVersion, Result};
+use crate::event::{notify, Event, Observer};
+use crate::transport::{HttpTransport, Transport};
+use crate::{Compression, DependencyGroup, Distro, Error, Link, LinkHash, Result};
+
+/// Check if the URL refers to a local file or directory.
+///
+/// URLs using the "file://" scheme and plain paths are local.
+pub fn is_local(url: &str) -> bool {
+    url.starts_with("file://") || !url.contains("://")
+}
+
+/// Get the filesystem path of a local URL.
+pub fn local_path(url: &str) -> &str {
+    match url.strip_prefix("file://") {
+        Some(path) => path,
+        None => url,
+    }
+}
 
 /// Get the timestamp when the URL was last modified.
+///
+/// The default [HttpTransport] is used.
+/// For local files, the modification time and the size are used as etag.
 pub async fn get_etag(url: &str) -> Result<String> {
-    let client = Client::new();
-    let response = client
-        .head(url)
-        .send()
-        .await
-        .map_err(|e| Error::from_reqwest(e, url))?;
-
-    if !response.status().is_success() {
-        return Err(Error::new(
-            &format!("Url {url} download failed!"),
-            crate::ErrorType::Download,
-        ));
+    HttpTransport::new().get_etag(url).await
+}
+
+/// State of an incremental hash calculation.
+enum HashState {
+    Sha512(Sha512),
+    Sha256(Sha256),
+    Sha1(Sha1),
+    Md5(md5::Context),
+}
+
+/// Incremental hasher for verifying the content of a Link.
+///
+/// The strongest hash of the Link which is contained in the accepted hashes is used.
+pub(crate) struct LinkHasher {
+    hash: LinkHash,
+    name: &'static str,
+    expected: String,
+    url: String,
+    state: HashState,
+}
+
+impl LinkHasher {
+    /// Create a hasher for the given Link, accepting only the given _hashes_.
+    pub(crate) fn new(link: &Link, hashes: &[LinkHash]) -> Result<LinkHasher> {
+        let mut allowed: Vec<&LinkHash> = hashes
+            .iter()
+            .filter(|hash| link.hashes.contains_key(hash))
+            .collect();
+        allowed.sort();
+
+        let (name, state) = match allowed.last() {
+            Some(LinkHash::Sha512) => ("SHA512", HashState::Sha512(Sha512::new())),
+            Some(LinkHash::Sha256) => ("SHA256", HashState::Sha256(Sha256::new())),
+            Some(LinkHash::Sha1) => ("SHA1", HashState::Sha1(Sha1::new())),
+            Some(LinkHash::Md5) => ("MD5", HashState::Md5(md5::Context::new())),
+            None => {
+                return Err(Error::new(
+                    &format!(
+                        "No accepted hash for URL {} provided! Accepted hashes are {:?}.",
+                        &link.url, hashes
+                    ),
+                    crate::ErrorType::Download,
+                ));
+            }
+        };
+
+        let hash = allowed[allowed.len() - 1].clone();
+
+        Ok(LinkHasher {
+            name,
+            expected: link.hashes[&hash].to_lowercase(),
+            hash,
+            url: link.url.clone(),
+            state,
+        })
+    }
+
+    /// Get the hash type used for the verification.
+    pub(crate) fn hash(&self) -> LinkHash {
+        self.hash.clone()
+    }
+
+    /// Add data to the hash calculation.
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match &mut self.state {
+            HashState::Sha512(sha512) => sha512.update(data),
+            HashState::Sha256(sha256) => sha256.update(data),
+            HashState::Sha1(sha1) => sha1.update(data),
+            HashState::Md5(md5) => md5.consume(data),
+        }
     }
 
-    let etag = match response.headers().get("etag") {
-        Some(etag) => etag,
-        None => {
-            return Err(Error::new(
-                &format!("No etag found in header of {url}!"),
+    /// Compare the hash of all data with the expected hash.
+    pub(crate) fn verify(self) -> Result<()> {
+        let data_hash = match self.state {
+            HashState::Sha512(sha512) => format!("{:x}", sha512.finalize()),
+            HashState::Sha256(sha256) => format!("{:x}", sha256.finalize()),
+            HashState::Sha1(sha1) => format!("{:x}", sha1.finalize()),
+            HashState::Md5(md5) => format!("{:x}", md5.compute()),
+        };
+
+        if self.expected != data_hash {
+            Err(Error::new(
+                &format!(
+                    "{} hash verification of URL {} failed!",
+                    self.name, &self.url
+                ),
                 crate::ErrorType::Download,
-            ));
+            ))
+        } else {
+            info!(
+                "Verified {} hash for URL {} successfully.",
+                self.name, &self.url
+            );
+            Ok(())
         }
-    };
+    }
+}
 
-    let etag = etag
-        .to_str()
-        .map_err(|e| Error::from_to_str_error(e, url))?;
+/// Run the operation for the URL on all mirrors of the distribution, until it succeeds.
+///
+/// The mirrors are tried in the order of [Distro::base_urls],
+/// and the mirror which served the URL is recorded.
+/// The error of the last mirror is returned if all mirrors fail.
+pub(crate) async fn on_mirrors<T, F, Fut>(distro: &Distro, url: &str, mut operation: F) -> Result<T>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let candidates = distro.mirror_candidates(url)?;
+    let count = candidates.len();
+    let mut last_error = None;
+
+    for (i, (mirror, mirror_url)) in candidates.into_iter().enumerate() {
+        match operation(mirror_url).await {
+            Ok(result) => {
+                distro.record_mirror(url, &mirror);
+                return Ok(result);
+            }
+            Err(e) => {
+                if i + 1 < count {
+                    warn!("Download of {url} from mirror {mirror} failed, trying next mirror. {e}");
+                }
+                last_error = Some(e);
+            }
+        }
+    }
 
-    Ok(etag.to_string())
+    Err(last_error.unwrap_or_else(|| {
+        Error::new(
+            &format!("No mirror for {url} available!"),
+            crate::ErrorType::Download,
+        )
+    }))
 }
 
-/// Download the content of the given URL as a String.
-pub async fn download(url: &str) -> Result<String> {
-    let client = Client::new();
-    Ok(client
-        .get(url)
-        .send()
-        .await
-        .map_err(|e| Error::from_reqwest(e, url))?
-        .text()
-        .await
-        .map_err(|e| Error::from_reqwest(e, url))?)
+/// Get the Link of the given Link on a mirror.
+pub(crate) fn mirror_link(link: &Link, url: String) -> Link {
+    Link {
+        url,
+        size: link.size,
+        hashes: link.hashes.clone(),
+    }
 }
 
 /// Verify the hash of the downloaded content for the given Link.
-fn verify_hash(content: &Vec<u8>, link: &Link) -> Result<()> {
-    let (name, hash, data_hash) = if let Some(hash) = link.hashes.get(&LinkHash::Sha512) {
-        let mut sha512 = Sha512::new();
-        sha512.update(content);
-        let data_hash = sha512.finalize();
-        let data_hash = format!("{:x}", data_hash);
-
-        ("SHA512", hash.clone(), data_hash)
-    } else if let Some(hash) = link.hashes.get(&LinkHash::Sha256) {
-        let mut sha256 = Sha256::new();
-        sha256.update(content);
-        let data_hash = sha256.finalize();
-        let data_hash = format!("{:x}", data_hash);
-
-        ("SHA256", hash.clone(), data_hash)
-    } else if let Some(hash) = link.hashes.get(&LinkHash::Sha1) {
-        let mut sha1 = Sha1::new();
-        sha1.update(content);
-        let data_hash = sha1.finalize();
-        let data_hash = format!("{:x}", data_hash);
-
-        ("SHA1", hash.clone(), data_hash)
-    } else if let Some(hash) = link.hashes.get(&LinkHash::Md5) {
-        let digest = md5::compute(content);
-        let data_hash = format!("{:x}", digest);
-
-        ("MD5", hash.clone(), data_hash)
-    } else {
-        return Err(Error::new(
-            &format!("No hash for URL {} provided!", &link.url),
-            crate::ErrorType::Download,
-        ));
-    };
-
-    let hash = hash.to_lowercase();
+///
+/// The strongest hash of the Link which is contained in _hashes_ is used.
+/// The result is reported to the _observer_.
+fn verify_hash(
+    content: &[u8],
+    link: &Link,
+    hashes: &[LinkHash],
+    observer: &Option<Arc<dyn Observer>>,
+) -> Result<()> {
+    let mut hasher = LinkHasher::new(link, hashes)?;
+    let hash = hasher.hash();
+    hasher.update(content);
+    notify_verification(hasher.verify(), hash, link, observer)
+}
 
-    if hash != data_hash {
-        return Err(Error::new(
-            &format!("{} hash verification of URL {} failed!", name, &link.url),
-            crate::ErrorType::Download,
-        ));
-    } else {
-        info!("Verified {} hash for URL {} successfully.", name, &link.url);
-        Ok(())
+/// Report the result of a hash verification to the _observer_.
+pub(crate) fn notify_verification(
+    result: Result<()>,
+    hash: LinkHash,
+    link: &Link,
+    observer: &Option<Arc<dyn Observer>>,
+) -> Result<()> {
+    match &result {
+        Ok(()) => notify(observer, || Event::HashVerified {
+            url: link.url.clone(),
+            hash,
+        }),
+        Err(e) => notify(observer, || Event::HashFailed {
+            url: link.url.clone(),
+            error: e.clone(),
+        }),
     }
+    result
 }
 
-/// Download and decompress the content of the given URL as a String.
+/// Download the content of the URL and report the progress to the _observer_.
 ///
-/// The compression type is guessed using the extension.
-/// Known extensions are "xz" and "gz".
-/// In case of an unknown extension, no compression is guessed.
-pub async fn download_compressed(link: &Link) -> Result<String> {
-    let url = &link.url;
+/// The data is read in chunks using _Transport::open_, and each chunk is reported.
+/// The events use the _event_url_, e.g. the URL of the Link, which may differ from the downloaded _url_.
+/// The _size_ is the expected size in bytes, if known.
+async fn download_observed(
+    transport: &dyn Transport,
+    url: &str,
+    event_url: &str,
+    size: Option<usize>,
+    observer: &Option<Arc<dyn Observer>>,
+) -> Result<Vec<u8>> {
+    notify(observer, || Event::DownloadStarted {
+        url: event_url.to_string(),
+        size,
+    });
+
+    let mut reader = transport.open(url).await?;
+    let mut data = Vec::new();
+    while let Some(chunk) = reader.chunk().await? {
+        data.extend_from_slice(&chunk);
+        notify(observer, || Event::DownloadProgress {
+            url: event_url.to_string(),
+            received: data.len(),
+            size,
+        });
+    }
 
-    let client = Client::new();
+    notify(observer, || Event::DownloadFinished {
+        url: event_url.to_string(),
+        received: data.len(),
+    });
 
-    let result = client
-        .get(url)
-        .send()
-        .await
-        .map_err(|e| Error::from_reqwest(e, url))?;
+    Ok(data)
+}
 
-    let text = if url.ends_with(".xz") {
-        let data = result
-            .bytes()
-            .await
-            .map_err(|e| Error::from_reqwest(e, url))?;
+/// Download a file of the distribution, e.g. the InRelease file or a key,
+/// and report the progress to the observer of the _distro_.
+pub(crate) async fn download_file(distro: &Distro, url: &str) -> Result<Vec<u8>> {
+    download_observed(distro.transport.as_ref(), url, url, None, &distro.observer).await
+}
 
-        verify_hash(&data.to_vec(), link)?;
+/// Download a text file of the distribution, e.g. the InRelease file, as String,
+/// and report the progress to the observer of the _distro_.
+pub(crate) async fn download_text(distro: &Distro, url: &str) -> Result<String> {
+    let data = download_file(distro, url).await?;
+    String::from_utf8(data).map_err(|e| Error::from_utf8_error(e, url))
+}
 
-        let content = lzma::decompress(&data).map_err(|e| Error::from_lzma(e, url))?;
-        String::from_utf8_lossy(&content).to_string()
-    } else if url.ends_with(".gz") {
-        let data = result
-            .bytes()
-            .await
-            .map_err(|e| Error::from_reqwest(e, url))?;
+/// Download the content of the given Link from one mirror and verify the hash.
+///
+/// If _by_hash_ is true, the file is first fetched using the Acquire-By-Hash URL,
+/// and only if this fails the canonical URL is used.
+/// Only the given _hashes_ are accepted for verification.
+/// Content failing the verification is evicted from the cache of the transport.
+async fn download_verified(
+    distro: &Distro,
+    link: &Link,
+    by_hash: bool,
+    hashes: &[LinkHash],
+) -> Result<Vec<u8>> {
+    let transport = distro.transport.as_ref();
+    let observer = &distro.observer;
+
+    if by_hash {
+        if let Some(url) = link.by_hash_url() {
+            let result = download_observed(transport, &url, &link.url, Some(link.size), observer)
+                .await
+                .and_then(|data| {
+                    verify_hash(&data, link, hashes, observer).inspect_err(|_| {
+                        transport.evict(&url);
+                    })?;
+                    Ok(data)
+                });
+
+            match result {
+                Ok(data) => return Ok(data),
+                Err(e) => {
+                    info!("By-hash download {url} failed, using {}. {e}", &link.url);
+                }
+            }
+        }
+    }
 
-        verify_hash(&data.to_vec(), link)?;
+    let data =
+        download_observed(transport, &link.url, &link.url, Some(link.size), observer).await?;
+    verify_hash(&data, link, hashes, observer).inspect_err(|_| {
+        transport.evict(&link.url);
+    })?;
+    Ok(data)
+}
 
-        let mut gz = GzDecoder::new(&data[..]);
-        let mut text = String::new();
-        gz.read_to_string(&mut text)
-            .map_err(|e| Error::from_io_error(e, url))?;
-        text
-    } else {
-        info!("No known extension, assuming plain text.");
+/// Download and decompress the content of the given URL as a String.
+///
+/// The compression type is detected using the magic bytes of the data,
+/// and if no magic bytes are found, guessed using the extension.
+/// Known compressions are xz, zstd, gzip, bzip2 and lz4.
+/// In case of an unknown compression, plain text is assumed.
+///
+/// If _by_hash_ is true, the file is fetched using the Acquire-By-Hash URL if possible.
+/// Only the given _hashes_ are accepted for verification.
+/// If the download or the verification fails, the next mirror of the _distro_ is used.
+/// The progress is reported to the observer of the _distro_.
+pub async fn download_compressed(
+    distro: &Distro,
+    link: &Link,
+    by_hash: bool,
+    hashes: &[LinkHash],
+) -> Result<String> {
+    let url = &link.url;
 
-        let data = result
-            .bytes()
-            .await
-            .map_err(|e| Error::from_reqwest(e, url))?;
-        let data = data.to_vec();
+    let data = on_mirrors(distro, url, |mirror_url| async move {
+        download_verified(distro, &mirror_link(link, mirror_url), by_hash, hashes).await
+    })
+    .await?;
 
-        verify_hash(&data, link)?;
+    let compression =
+        Compression::from_magic(&data).unwrap_or_else(|| Compression::from_extension(url));
 
+    let text = if compression == Compression::None {
+        info!("No known compression, assuming plain text.");
         String::from_utf8(data).map_err(|e| Error::from_utf8_error(e, url))?
+    } else {
+        let content = compression.decompress(&data, url)?;
+        notify(&distro.observer, || Event::Decompressed {
+            url: url.clone(),
+            compression,
+            size: content.len(),
+        });
+        String::from_utf8_lossy(&content).to_string()
     };
 
     Ok(text)
@@ -232,26 +420,25 @@ pub fn parse_stanza(stanza: &str) -> HashMap<String, String> {
 }
 
 /// Parse a package dependency and relation field.
-pub fn parse_package_relation(depends: &str) -> Result<Vec<PackageVersion>> {
-    let pvs: Result<Vec<Vec<PackageVersion>>> = depends
-        .split(",")
-        .map(|p| p.trim())
-        .map(|p| PackageVersion::from_str(p))
-        .collect();
-    let pvs = pvs?;
-    let pvs: Vec<PackageVersion> = pvs.iter().flatten().map(|pv| pv.clone()).collect();
-
-    Ok(pvs)
+pub fn parse_package_relation(depends: &str) -> Result<DependencyGroup> {
+    depends.parse()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::temp_dir;
+    use crate::Key;
 
     #[tokio::test]
     async fn download_ubuntu_jammy_release_metadata() {
+        let distro = Distro::repo(
+            "http://archive.ubuntu.com/ubuntu",
+            "jammy",
+            Key::NoSignatureCheck,
+        );
         let url = "http://archive.ubuntu.com/ubuntu/dists/jammy/Release";
-        let text = download(url).await.unwrap();
+        let text = download_text(&distro, url).await.unwrap();
         assert!(!text.is_empty(), "Content is not empty");
     }
 
@@ -274,6 +461,68 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn download_local_file() {
+        use std::fs;
+
+        let dir = temp_dir("util");
+        let distro = Distro::repo(dir.to_str().unwrap(), "local", Key::NoSignatureCheck);
+        let path = dir.join("Release");
+        fs::write(&path, "Origin: Local\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let text = download_text(&distro, path).await.unwrap();
+        assert_eq!(text, "Origin: Local\n");
+
+        let text = download_text(&distro, &format!("file://{path}"))
+            .await
+            .unwrap();
+        assert_eq!(text, "Origin: Local\n");
+
+        get_etag(&format!("file://{path}")).await.unwrap();
+
+        // File doesn't exist.
+        assert!(get_etag(&format!("file://{path}.xz")).await.is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn verify_accepted_hashes() {
+        let content = b"Package: hello\n".to_vec();
+        let strong = vec![LinkHash::Sha512, LinkHash::Sha256];
+
+        let mut link = Link {
+            url: "main/binary-amd64/Packages".to_string(),
+            size: content.len(),
+            hashes: HashMap::new(),
+        };
+        link.hashes
+            .insert(LinkHash::Md5, format!("{:x}", md5::compute(&content)));
+
+        // MD5 only index is rejected.
+        assert!(verify_hash(&content, &link, &strong, &None).is_err());
+        verify_hash(&content, &link, &[LinkHash::Md5], &None).unwrap();
+
+        // The strongest accepted hash is used.
+        link.hashes.insert(LinkHash::Sha1, "0000".to_string());
+        link.hashes
+            .insert(LinkHash::Sha256, format!("{:X}", Sha256::digest(&content)));
+        verify_hash(&content, &link, &strong, &None).unwrap();
+        assert!(verify_hash(&content, &link, &[LinkHash::Sha1, LinkHash::Md5], &None).is_err());
+    }
+
+    #[test]
+    fn test_is_local() {
+        assert!(is_local("file:///srv/mirror"));
+        assert!(is_local("/srv/mirror"));
+        assert!(is_local("mirror"));
+        assert!(!is_local("http://archive.ubuntu.com/ubuntu"));
+        assert!(!is_local("https://archive.ubuntu.com/ubuntu"));
+        assert_eq!(local_path("file:///srv/mirror"), "/srv/mirror");
+        assert_eq!(local_path("/srv/mirror"), "/srv/mirror");
+    }
+
     #[tokio::test]
     async fn test_get_etag() {
         let etag = get_etag("http://archive.ubuntu.com/ubuntu/dists/noble/InRelease")
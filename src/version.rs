@@ -8,59 +8,78 @@ use std::println as error;
 
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::iter::zip;
 
 use crate::{Error, ErrorType, Result};
 
-/// Split a Debian package version or revision into parts.
+/// Get the sort weight of a non-digit character, like _order_ of dpkg.
 ///
-/// Parts are either sequences for numbers, separators or characters.
-fn split_parts(version: &str) -> Vec<String> {
-    let mut result: Vec<String> = Vec::new();
-
-    if version == "" {
-        return result;
+/// The tilde sorts before everything, even the end of a part,
+/// letters sort before all other characters.
+fn order(c: Option<u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(b'~') => -1,
+        Some(c) if c.is_ascii_digit() => 0,
+        Some(c) if c.is_ascii_alphabetic() => c as i32,
+        Some(c) => c as i32 + 256,
     }
+}
 
-    let chars: Vec<char> = version.chars().collect();
-
-    let mut digit = chars[0].is_ascii_digit();
+/// Compare two digit sequences of arbitrary length numerically.
+fn compare_digits(a: &[u8], b: &[u8]) -> Ordering {
+    let a = &a[a.iter().take_while(|c| **c == b'0').count()..];
+    let b = &b[b.iter().take_while(|c| **c == b'0').count()..];
 
-    let mut part = String::new();
-    for c in version.chars() {
-        if (digit && c.is_ascii_digit()) || (!digit && !c.is_ascii_digit()) {
-            part.push(c);
-        } else {
-            result.push(part);
-            digit = !digit;
+    // without leading zeros, the longer number is the bigger one
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
 
-            part = String::new();
-            part.push(c);
+/// Check if the version part only contains the allowed characters.
+fn check_characters(part: &str, name: &str, allowed: &str) -> Result<()> {
+    match part
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && !allowed.contains(*c))
+    {
+        Some(c) => {
+            let message = format!("Invalid character '{c}' in {name} {part}!");
+            error!("{}", &message);
+            Err(Error::new(&message, ErrorType::Version))
         }
+        None => Ok(()),
     }
-    result.push(part);
-
-    result
 }
 
 /// The Version struct groups the Debian version parts.
 #[derive(Eq, Debug, Clone, Deserialize, Serialize)]
 pub struct Version {
     // see https://www.debian.org/doc/debian-policy/ch-controlfields.html#version
-    pub epoch: Option<u8>,
+    pub epoch: Option<u32>,
     pub version: String,
     pub revision: Option<String>,
 }
 
 impl Version {
     /// Split Debian version string into epoch, version and revision.
-    fn split(version: &str) -> Result<(Option<u8>, String, Option<String>)> {
+    ///
+    /// The parts are validated according to the Debian policy:
+    /// the epoch is a number, the upstream version starts with a digit
+    /// and uses only alphanumerics and _. + ~ - :_, and the revision
+    /// uses only alphanumerics and _. + ~_.
+    fn split(version: &str) -> Result<(Option<u32>, String, Option<String>)> {
+        let version = version.trim();
+
         let (epoch, version) = match version.find(':') {
             Some(pos) => {
                 let epoch = &version[..pos];
                 let version = &version[(pos + 1)..];
 
-                let epoch = match epoch.parse::<u8>() {
+                if epoch.is_empty() || !epoch.chars().all(|c| c.is_ascii_digit()) {
+                    let message = format!("Parse epoch error! Epoch '{epoch}' is not a number.");
+                    error!("{}", &message);
+                    return Err(Error::new(&message, ErrorType::Version));
+                }
+
+                let epoch = match epoch.parse::<u32>() {
                     Ok(epoch) => epoch,
                     Err(e) => {
                         let message = format!("Parse epoch error! {e}");
@@ -79,11 +98,25 @@ impl Version {
                 let revision = &version[(pos + 1)..];
                 let version = &version[..pos];
 
+                if revision.is_empty() {
+                    let message = format!("Revision of version {version}- is empty!");
+                    error!("{}", &message);
+                    return Err(Error::new(&message, ErrorType::Version));
+                }
+                check_characters(revision, "revision", ".+~")?;
+
                 (version, Some(revision.to_string()))
             }
             None => (version, None),
         };
 
+        if !version.starts_with(|c: char| c.is_ascii_digit()) {
+            let message = format!("Version '{version}' does not start with a digit!");
+            error!("{}", &message);
+            return Err(Error::new(&message, ErrorType::Version));
+        }
+        check_characters(version, "version", ".+~-:")?;
+
         Ok((epoch, version.to_string(), revision))
     }
 
@@ -102,31 +135,8 @@ impl Version {
 
     /// Compare two epochs.
     fn compare_epoch(&self, other: &Version) -> Ordering {
-        if self.epoch == other.epoch {
-            return Ordering::Equal;
-        }
-
-        // unpack numbers
-        let self_epoch = match self.epoch {
-            Some(epoch) => epoch,
-            None => 0,
-        };
-        let other_epoch = match other.epoch {
-            Some(epoch) => epoch,
-            None => 0,
-        };
-
-        if self_epoch > other_epoch {
-            Ordering::Greater
-        } else if self_epoch < other_epoch {
-            Ordering::Less
-        } else {
-            assert!(
-                false,
-                "This should not happen, equality is checked as very first case."
-            );
-            Ordering::Equal
-        }
+        // a missing epoch is the epoch 0
+        self.epoch.unwrap_or(0).cmp(&other.epoch.unwrap_or(0))
     }
 
     /// Compare two versions.
@@ -144,7 +154,7 @@ impl Version {
             return Ordering::Equal;
         }
 
-        // Add empty string as last element marker
+        // a missing revision is the empty string
         let self_revision = match &self.revision {
             Some(revision) => revision,
             None => "",
@@ -158,113 +168,58 @@ impl Version {
         Version::compare_version_str(self_revision, other_revision)
     }
 
-    /// Implementation of the version and revision comparison.
+    /// Implementation of the version and revision comparison,
+    /// following _verrevcmp_ of dpkg.
+    ///
+    /// The strings are compared in alternating non-digit and digit parts.
+    /// Non-digit parts are compared character by character using [order],
+    /// digit parts are compared numerically.
     fn compare_version_str(self_version: &str, other_version: &str) -> Ordering {
-        if self_version == other_version {
-            return Ordering::Equal;
-        }
+        let a = self_version.as_bytes();
+        let b = other_version.as_bytes();
 
-        let mut self_parts = split_parts(self_version);
-        let mut other_parts = split_parts(other_version);
+        let mut i = 0;
+        let mut j = 0;
 
-        if self_parts.len() < other_parts.len() {
-            self_parts.push(String::new());
-        } else if self_parts.len() > other_parts.len() {
-            other_parts.push(String::new());
-        }
+        while i < a.len() || j < b.len() {
+            // compare non-digit parts
+            while (i < a.len() && !a[i].is_ascii_digit()) || (j < b.len() && !b[j].is_ascii_digit())
+            {
+                let ac = order(a.get(i).copied());
+                let bc = order(b.get(j).copied());
 
-        for (s, o) in zip(self_parts, other_parts) {
-            if s == o {
-                continue;
+                if ac != bc {
+                    return ac.cmp(&bc);
+                }
+
+                i += 1;
+                j += 1;
             }
 
-            if s == "" {
-                return Ordering::Less;
+            // compare digit parts
+            let a_start = i;
+            while i < a.len() && a[i].is_ascii_digit() {
+                i += 1;
             }
-            if o == "" {
-                return Ordering::Greater;
+            let b_start = j;
+            while j < b.len() && b[j].is_ascii_digit() {
+                j += 1;
             }
 
-            // parse numbers
-            let sn = match s.parse::<u32>() {
-                Ok(sn) => Some(sn),
-                Err(_) => None,
-            };
-            let on = match o.parse::<u32>() {
-                Ok(so) => Some(so),
-                Err(_) => None,
-            };
-
-            if sn != None && on == None {
-                return Ordering::Greater;
-            } else if sn == None && on != None {
-                return Ordering::Less;
-            } else if sn != None && on != None {
-                // compare numbers
-                if let Some(sn) = sn {
-                    if let Some(on) = on {
-                        if sn < on {
-                            return Ordering::Less;
-                        } else if sn > on {
-                            return Ordering::Greater;
-                        }
-                    }
-                }
-            } else {
-                // compare strings
-                let mut s = s.to_string();
-                let mut o = o.to_string();
-
-                let sl = s.len();
-                let ol = o.len();
-
-                let s = if sl < ol {
-                    s.push('~');
-                    s.chars()
-                } else {
-                    s.chars()
-                };
-                let o = if ol < sl {
-                    o.push('~');
-                    o.chars()
-                } else {
-                    o.chars()
-                };
-
-                for (sc, oc) in zip(s, o) {
-                    if sc == oc {
-                        continue;
-                    }
-
-                    if sc == '~' {
-                        return Ordering::Less;
-                    }
-                    if oc == '~' {
-                        return Ordering::Greater;
-                    }
-
-                    if sc < oc {
-                        return Ordering::Less;
-                    } else if sc > oc {
-                        return Ordering::Greater;
-                    }
-                }
+            match compare_digits(&a[a_start..i], &b[b_start..j]) {
+                Ordering::Equal => {}
+                ordering => return ordering,
             }
         }
 
-        assert!(
-            false,
-            "This should not happen, equality is checked as very first case."
-        );
         Ordering::Equal
     }
 }
 
 impl PartialEq for Version {
+    /// Versions are equal if dpkg considers them equal, e.g. _1.0_ and _0:1.0-0_.
     fn eq(&self, other: &Version) -> bool {
-        self.epoch == other.epoch
-            && self.version == other.version
-            && self.revision == other.revision
+        self.cmp(other) == Ordering::Equal
     }
 }
 
@@ -276,10 +231,6 @@ impl PartialOrd for Version {
 
 impl Ord for Version {
     fn cmp(&self, other: &Version) -> Ordering {
-        if self == other {
-            return Ordering::Equal;
-        }
-
         match self.compare_epoch(other) {
             Ordering::Equal => {} // comparing versions required
             Ordering::Greater => return Ordering::Greater,
@@ -303,25 +254,7 @@ impl Ord for Version {
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    #[test]
-    fn test_split_parts() {
-        let version = "2.0.12";
-        let parts = split_parts(version);
-        assert_eq!(parts.len(), 5);
-        assert_eq!(parts[0], "2");
-        assert_eq!(parts[1], ".");
-        assert_eq!(parts[2], "0");
-        assert_eq!(parts[3], ".");
-        assert_eq!(parts[4], "12");
-
-        let version = "1ubuntu1";
-        let parts = split_parts(version);
-        assert_eq!(parts.len(), 3);
-        assert_eq!(parts[0], "1");
-        assert_eq!(parts[1], "ubuntu");
-        assert_eq!(parts[2], "1");
-    }
+    use crate::test_util::test_data;
 
     #[test]
     fn parse_version() {
@@ -418,4 +351,113 @@ mod tests {
         let vd = Version::from_str("1.66~").unwrap();
         assert!(vd < vp, "compare versions");
     }
+
+    /// Read the conformance corpus of dpkg version comparisons.
+    fn comparisons() -> Vec<(Version, Ordering, Version)> {
+        let content = std::fs::read_to_string(test_data("versions.txt")).unwrap();
+
+        content
+            .lines()
+            .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+            .map(|l| {
+                let parts: Vec<&str> = l.split_whitespace().collect();
+                let ordering = match parts[1] {
+                    "<" => Ordering::Less,
+                    "=" => Ordering::Equal,
+                    ">" => Ordering::Greater,
+                    op => panic!("Unknown ordering {op}"),
+                };
+                (
+                    Version::from_str(parts[0]).unwrap(),
+                    ordering,
+                    Version::from_str(parts[2]).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dpkg_conformance() {
+        for (a, ordering, b) in comparisons() {
+            assert_eq!(a.cmp(&b), ordering, "{a:?} {ordering:?} {b:?}");
+            assert_eq!(b.cmp(&a), ordering.reverse(), "{b:?} {a:?}");
+            assert_eq!(a == b, ordering == Ordering::Equal, "{a:?} == {b:?}");
+        }
+    }
+
+    #[test]
+    fn ordering_properties() {
+        let versions: Vec<Version> = comparisons()
+            .into_iter()
+            .flat_map(|(a, _, b)| [a, b])
+            .collect();
+
+        for a in &versions {
+            assert_eq!(a.cmp(a), Ordering::Equal, "reflexive {a:?}");
+
+            for b in &versions {
+                // antisymmetric
+                assert_eq!(a.cmp(b), b.cmp(a).reverse(), "{a:?} {b:?}");
+
+                // transitive
+                for c in &versions {
+                    if a <= b && b <= c {
+                        assert!(a <= c, "{a:?} <= {b:?} <= {c:?}");
+                    }
+                }
+            }
+        }
+
+        let mut sorted = versions.clone();
+        sorted.sort();
+        for pair in sorted.windows(2) {
+            assert!(pair[0] <= pair[1], "{:?} <= {:?}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn long_numbers_and_epochs() {
+        let a = Version::from_str("20240101123456789012345678901234567890").unwrap();
+        let b = Version::from_str("20240101123456789012345678901234567891").unwrap();
+        assert!(a < b);
+
+        let v = Version::from_str("300:1.0").unwrap();
+        assert_eq!(v.epoch, Some(300));
+        assert!(v > Version::from_str("255:1.0").unwrap());
+
+        assert_eq!(
+            Version::from_str("1.0").unwrap(),
+            Version::from_str("0:1.0-0").unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_versions() {
+        for version in [
+            "",
+            "a1.0",
+            "1.0_1",
+            "1.0 2",
+            "1.0-",
+            "1.0-1:2",
+            "1.0-1_1",
+            ":1.0",
+            "a:1.0",
+            "-1:1.0",
+            "99999999999:1.0",
+            "1.0/2",
+        ] {
+            assert!(Version::from_str(version).is_err(), "{version}");
+        }
+
+        for version in [
+            "1.0",
+            "1:1.0-1",
+            "1.0~rc1+dfsg.1-1~bpo1",
+            "1:2:3-1",
+            "1.0-1-1",
+        ] {
+            assert!(Version::from_str(version).is_ok(), "{version}");
+        }
+    }
 }
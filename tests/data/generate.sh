@@ -0,0 +1,181 @@
+#!/bin/bash
+#
+# Generate the signed test repositories and keys used by the unit tests.
+#
+# The keys are test keys only and have no passphrase.
+# The secret keys are stored in keys/secret.asc, and reused if the
+# script is run again, so that only the signatures change.
+
+set -e
+
+cd "$(dirname "$0")"
+
+export GNUPGHOME=$(mktemp -d)
+trap 'rm -rf "$GNUPGHOME"' EXIT
+
+mkdir -p keys
+
+if [ -f keys/secret.asc ]; then
+    gpg --batch --import keys/secret.asc
+fi
+
+# Generate a key if it doesn't exist.
+# $1: key name
+# $2: algorithm
+# $3: usage of the primary key
+# $4: expiration, default is never
+# $@: additional gpg arguments
+ensure_key() {
+    local name="$1" algo="$2" usage="$3" expire="${4:-never}"
+    shift 4 || shift $#
+    if ! gpg --list-keys "$name@libapt.invalid" > /dev/null 2>&1; then
+        gpg --batch --passphrase '' "$@" --quick-gen-key "libapt $name <$name@libapt.invalid>" "$algo" "$usage" "$expire"
+        return 0
+    fi
+    return 1
+}
+
+# Get the fingerprint of the primary key.
+# $1: key name
+fingerprint() {
+    gpg --with-colons --fingerprint "$1@libapt.invalid" | grep '^fpr' | head -n 1 | cut -d: -f10
+}
+
+# Get the fingerprint of the signing subkey.
+# $1: key name
+subkey_fingerprint() {
+    gpg --with-colons --fingerprint "$1@libapt.invalid" | grep '^fpr' | sed -n 2p | cut -d: -f10
+}
+
+# Signing key of the test repositories.
+ensure_key test rsa3072 sign || true
+
+# Key not used for signing.
+ensure_key other rsa3072 sign || true
+
+# Key which signs using a subkey.
+if ensure_key subkey rsa3072 cert; then
+    gpg --batch --passphrase '' --quick-add-key "$(fingerprint subkey)" rsa3072 sign never
+fi
+
+# Keys which sign using a subkey, which is revoked or loses the signing flag after signing.
+for name in revoked unflagged; do
+    if ensure_key "$name" rsa3072 cert; then
+        gpg --batch --passphrase '' --quick-add-key "$(fingerprint "$name")" rsa3072 sign never
+    fi
+done
+
+# Key which is too small.
+ensure_key small rsa1024 sign || true
+
+# Key which expired at the end of 2020.
+EXPIRED_TIME="20200101T000000!"
+ensure_key expired rsa3072 sign 1y --faked-system-time "$EXPIRED_TIME" || true
+
+gpg --batch --armor --export-secret-keys > keys/secret.asc
+
+for name in test other subkey small expired; do
+    gpg --armor --export "$name@libapt.invalid" > "keys/$name.asc"
+    gpg --export "$name@libapt.invalid" > "keys/$name.gpg"
+    fingerprint "$name" > "keys/$name.fpr"
+done
+subkey_fingerprint subkey > keys/subkey-sign.fpr
+
+# Keyrings containing several keys.
+gpg --export test@libapt.invalid other@libapt.invalid > keys/keyring.gpg
+cat keys/other.asc keys/test.asc > keys/keyring.asc
+
+# Key directory, like /etc/apt/trusted.gpg.d.
+rm -rf keys/trusted.gpg.d
+mkdir -p keys/trusted.gpg.d
+cp keys/other.asc keys/subkey.gpg keys/trusted.gpg.d/
+cp keys/test.gpg keys/trusted.gpg.d/test.gpg
+echo "Not a key." > keys/trusted.gpg.d/README
+
+rm -rf repo
+
+# Write a distribution with a single package index.
+# $1: distribution name
+write_dist() {
+    local dist="repo/dists/$1"
+    mkdir -p "$dist/main/binary-amd64"
+
+    cat > "$dist/main/binary-amd64/Packages" <<PACKAGES
+Package: hello
+Architecture: amd64
+Version: 2.10-2
+Maintainer: Santiago Vila <sanvila@debian.org>
+Filename: pool/main/h/hello/hello_2.10-2_amd64.deb
+Size: 56132
+SHA256: 35b1508eeee9c1dfba798c4c04304ef0f266990f936a51f165571edf53325cbc
+Description: example package based on GNU hello
+PACKAGES
+
+    gzip -9 -n -k "$dist/main/binary-amd64/Packages"
+
+    local packages="main/binary-amd64/Packages"
+    cat > "$dist/Release" <<RELEASE
+Origin: libapt
+Label: libapt
+Suite: $1
+Codename: $1
+Date: Mon, 01 Jan 2024 00:00:00 UTC
+Architectures: amd64
+Components: main
+Description: libapt test repository
+SHA256:
+ $(sha256sum "$dist/$packages" | cut -d' ' -f1) $(stat -c %s "$dist/$packages") $packages
+ $(sha256sum "$dist/$packages.gz" | cut -d' ' -f1) $(stat -c %s "$dist/$packages.gz") $packages.gz
+RELEASE
+}
+
+# Sign the Release file of a distribution as InRelease.
+# $1: distribution name
+# $@: additional gpg arguments
+clearsign() {
+    local dist="repo/dists/$1"
+    shift
+    gpg --batch --yes "$@" --clearsign -o "$dist/InRelease" "$dist/Release"
+    rm "$dist/Release"
+}
+
+# Distribution with inline signed InRelease.
+write_dist stable
+clearsign stable -u test@libapt.invalid --digest-algo SHA256
+
+# Distribution with Release and detached Release.gpg signature.
+write_dist legacy
+gpg --batch --yes -u test@libapt.invalid --digest-algo SHA256 --armor --detach-sign -o repo/dists/legacy/Release.gpg repo/dists/legacy/Release
+
+# Distribution signed by two keys, like during a key rotation.
+write_dist rotation
+clearsign rotation -u test@libapt.invalid -u other@libapt.invalid --digest-algo SHA256
+
+# Distribution signed using a subkey.
+write_dist subkey
+clearsign subkey -u "$(subkey_fingerprint subkey)!" --digest-algo SHA256
+
+# Distribution signed using the weak SHA1 digest.
+write_dist weak
+clearsign weak -u test@libapt.invalid --digest-algo SHA1
+
+# Distribution signed using a too small key.
+write_dist small
+clearsign small -u small@libapt.invalid --digest-algo SHA256
+
+# Distribution signed using a key which is expired now.
+write_dist expired
+clearsign expired -u expired@libapt.invalid --digest-algo SHA256 --faked-system-time "20200601T000000!"
+
+# Distributions signed using a subkey, which is revoked or loses the signing flag afterwards.
+for name in revoked unflagged; do
+    write_dist "$name"
+    clearsign "$name" -u "$(subkey_fingerprint "$name")!" --digest-algo SHA256
+done
+
+# Change the subkeys only after signing, the exported secret keys keep the original subkeys.
+printf 'key 1\nrevkey\ny\n0\n\ny\nsave\n' | gpg --batch --command-fd 0 --edit-key "$(fingerprint revoked)"
+printf 'key 1\nchange-usage\nS\nE\nQ\nsave\n' | gpg --batch --command-fd 0 --edit-key "$(fingerprint unflagged)"
+for name in revoked unflagged; do
+    gpg --armor --export "$name@libapt.invalid" > "keys/$name.asc"
+done